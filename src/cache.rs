@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomically, CompileCommand, Entry, Result};
+
+/// Extension appended to the name of a joined database to get the name of its cache file.
+const CACHE_EXTENSION: &str = "cache";
+
+/// Version of the cache file format, caches of other versions are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// Size and modification time of a file, which change whenever the file gets rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    size: u64,
+    modified: SystemTime,
+}
+
+impl Fingerprint {
+    fn of(metadata: &fs::Metadata) -> Option<Self> {
+        Some(Fingerprint {
+            size: metadata.len(),
+            modified: metadata.modified().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedInput {
+    path: PathBuf,
+    fingerprint: Fingerprint,
+    /// Hash of the contents, so rewriting a file with the same contents doesn't count as a change.
+    hash: u64,
+    entries: Vec<CompileCommand>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    settings: u64,
+    output: Option<Fingerprint>,
+    inputs: Vec<CachedInput>,
+}
+
+/// Returns the path of the cache file kept next to `database`.
+pub fn cache_path<P>(database: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let database = database.as_ref();
+    let mut name = database.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(CACHE_EXTENSION);
+    database.with_file_name(name)
+}
+
+/// Parsed entries of the inputs of a previous run, stored next to the joined database.
+///
+/// Inputs with the same size and modification time as in the previous run aren't read again, inputs with the same
+/// contents aren't parsed again. When all inputs of a run come from the cache and the joined database hasn't been
+/// touched since, [`InputCache::is_up_to_date`] tells there's no need to write it again.
+///
+/// Inputs can be loaded from several threads at once.
+#[derive(Debug)]
+pub struct InputCache {
+    /// Hash of everything besides the inputs influencing the joined database, like the options of the run.
+    settings: u64,
+    /// Inputs of the previous run.
+    cached: HashMap<PathBuf, CachedInput>,
+    /// Fingerprint of the joined database written by the previous run.
+    output: Option<Fingerprint>,
+    /// Inputs loaded by this run.
+    current: Mutex<BTreeMap<PathBuf, CachedInput>>,
+    /// Whether any input loaded by this run differs from the previous run.
+    changed: AtomicBool,
+    /// Whether any input has been rewritten with the same contents, so just its fingerprint changed.
+    touched: AtomicBool,
+}
+
+impl InputCache {
+    /// Loads the cache kept next to `database`.
+    ///
+    /// A missing or unreadable cache, or one written with different `settings`, is treated as empty.
+    pub fn load<P>(database: P, settings: u64) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let cache = fs::read(cache_path(database))
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<CacheFile>(&contents).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.settings == settings);
+        let (cached, output) = match cache {
+            Some(cache) => (
+                cache
+                    .inputs
+                    .into_iter()
+                    .map(|input| (input.path.clone(), input))
+                    .collect(),
+                cache.output,
+            ),
+            None => (HashMap::new(), None),
+        };
+        InputCache {
+            settings,
+            cached,
+            output,
+            current: Mutex::new(BTreeMap::new()),
+            changed: AtomicBool::new(false),
+            touched: AtomicBool::new(false),
+        }
+    }
+
+    /// Loads the entries of the database file at `path` like [`load_entries`](crate::load_entries), reusing the
+    /// cached ones if the file hasn't changed.
+    pub fn load_entries<P>(&self, path: P) -> Result<Vec<Entry>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let error = |err: io::Error| format!("{}: {}", path.display(), err);
+        let fingerprint = Fingerprint::of(&fs::metadata(path).map_err(error)?);
+        let cached = self.cached.get(path);
+
+        let input = match cached.filter(|cached| Some(cached.fingerprint) == fingerprint) {
+            Some(cached) => cached.clone(),
+            None => {
+                let contents = fs::read(path).map_err(error)?;
+                let hash = content_hash(&contents);
+                let entries = match cached.filter(|cached| cached.hash == hash) {
+                    Some(cached) => {
+                        self.touched.store(true, Ordering::Relaxed);
+                        cached.entries.clone()
+                    }
+                    None => {
+                        self.changed.store(true, Ordering::Relaxed);
+                        serde_json::from_slice(&contents)
+                            .map_err(|err| format!("{}: {}", path.display(), err))?
+                    }
+                };
+                CachedInput {
+                    path: path.to_path_buf(),
+                    // files without a modification time are read every time, the hash still saves parsing
+                    fingerprint: fingerprint.unwrap_or(Fingerprint {
+                        size: contents.len() as u64,
+                        modified: SystemTime::UNIX_EPOCH,
+                    }),
+                    hash,
+                    entries,
+                }
+            }
+        };
+
+        let input_path: Arc<Path> = Arc::from(path);
+        let entries = input
+            .entries
+            .iter()
+            .map(|command| Entry {
+                input: input_path.clone(),
+                command: command.clone(),
+            })
+            .collect();
+        self.current
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), input);
+        Ok(entries)
+    }
+
+    /// Checks whether `database` still is what the previous run wrote, joined from the inputs loaded by this run.
+    ///
+    /// That's the case if exactly the same inputs as in the previous run have been loaded, none of them changed and
+    /// `database` hasn't been modified since.
+    pub fn is_up_to_date<P>(&self, database: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let current = self.current.lock().unwrap();
+        !self.changed.load(Ordering::Relaxed)
+            && current.len() == self.cached.len()
+            && current.keys().all(|path| self.cached.contains_key(path))
+            && self.output.is_some()
+            && fs::metadata(database)
+                .ok()
+                .and_then(|metadata| Fingerprint::of(&metadata))
+                == self.output
+    }
+
+    /// Stores the inputs loaded by this run next to the just written (or [up to date](InputCache::is_up_to_date))
+    /// `database`.
+    ///
+    /// Nothing is stored if the stored cache is still accurate or for databases which aren't regular files (like
+    /// `/dev/stdout`).
+    pub fn save<P>(&self, database: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let database = database.as_ref();
+        if !self.touched.load(Ordering::Relaxed) && self.is_up_to_date(database) {
+            return Ok(());
+        }
+        let metadata = fs::metadata(database)?;
+        if !metadata.is_file() {
+            return Ok(());
+        }
+        let cache = CacheFile {
+            version: CACHE_VERSION,
+            settings: self.settings,
+            output: Fingerprint::of(&metadata),
+            inputs: self.current.lock().unwrap().values().cloned().collect(),
+        };
+        write_atomically(cache_path(database), |file| {
+            let mut output = io::BufWriter::new(file);
+            serde_json::to_writer(&mut output, &cache)?;
+            output.flush()?;
+            Ok(())
+        })
+    }
+}
+
+/// 64-bit FNV-1a hash, which unlike the hashers of the standard library is guaranteed to stay the same.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
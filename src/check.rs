@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::dedup::{duplicate_key, DuplicateKey};
+use crate::join::{for_each_element, ElementError};
+use crate::paths::{absolute, input_directory};
+use crate::{shell, CompileCommand, Entry, ShellError};
+
+/// A problem with an input database or one of its entries, found by [`check_inputs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Path of the database file.
+    pub input: Arc<Path>,
+
+    /// Position of the entry within the database, `None` for problems of the whole database.
+    pub index: Option<usize>,
+
+    /// `file` of the entry, if it has a valid one.
+    pub file: Option<PathBuf>,
+
+    /// What's wrong.
+    pub kind: ProblemKind,
+}
+
+/// Kinds of [`Problem`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The database can't be read or isn't a JSON list.
+    Unreadable(String),
+    /// The entry isn't a JSON object.
+    NotAnObject,
+    /// A required field is missing.
+    MissingField(&'static str),
+    /// A field has the wrong type.
+    InvalidField(&'static str),
+    /// The entry has neither `command` nor `arguments`.
+    NoCommand,
+    /// The `command` can't be split into arguments.
+    InvalidCommand(ShellError),
+    /// A path field isn't absolute.
+    RelativePath(&'static str),
+    /// The `directory` (resolved to the given path) doesn't exist.
+    MissingDirectory(PathBuf),
+    /// The `file` (resolved to the given path) doesn't exist.
+    MissingFile(PathBuf),
+    /// The entry describes the same compilation as an earlier one, at the given input and position.
+    Duplicate(Arc<Path>, usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.input.display())?;
+        if let Some(index) = self.index {
+            write!(f, "[{}]", index)?;
+        }
+        if let Some(file) = &self.file {
+            write!(f, " ({})", file.display())?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemKind::Unreadable(reason) => write!(f, "{}", reason),
+            ProblemKind::NotAnObject => write!(f, "entry is not an object"),
+            ProblemKind::MissingField(field) => write!(f, "missing `{}`", field),
+            ProblemKind::InvalidField(field) => write!(f, "invalid `{}`", field),
+            ProblemKind::NoCommand => write!(f, "neither `command` nor `arguments` given"),
+            ProblemKind::InvalidCommand(err) => write!(f, "{}", err),
+            ProblemKind::RelativePath(field) => write!(f, "`{}` is not absolute", field),
+            ProblemKind::MissingDirectory(path) => {
+                write!(f, "directory {} does not exist", path.display())
+            }
+            ProblemKind::MissingFile(path) => write!(f, "file {} does not exist", path.display()),
+            ProblemKind::Duplicate(input, index) => {
+                write!(f, "duplicate of {}[{}]", input.display(), index)
+            }
+        }
+    }
+}
+
+/// Checks all entries of the databases at `inputs`.
+///
+/// Entries are parsed the same way as for joining, but one malformed entry doesn't stop the others from being
+/// checked. Reported are databases which can't be parsed, entries with missing or invalid fields, without a
+/// command, with paths which aren't absolute or don't exist and entries describing the same compilation (the same
+/// directory, file and output) as an earlier one.
+pub fn check_inputs<I, P>(inputs: I) -> Vec<Problem>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut problems = Vec::new();
+    let mut seen: HashMap<DuplicateKey, (Arc<Path>, usize)> = HashMap::new();
+    for input in inputs {
+        let input: Arc<Path> = Arc::from(input.as_ref());
+        // entries are checked as they are read, so databases of any size can be checked
+        let mut index = 0;
+        let result = for_each_element(&input, |value| {
+            check_entry(&input, index, value, &mut seen, &mut problems);
+            index += 1;
+            Ok(())
+        });
+        if let Err(err) = result {
+            let reason = match err {
+                ElementError::Read(reason) => reason,
+                ElementError::Callback(err) => err.to_string(),
+            };
+            problems.push(Problem {
+                input: input.clone(),
+                index: None,
+                file: None,
+                kind: ProblemKind::Unreadable(reason),
+            });
+        }
+    }
+    problems
+}
+
+/// Checks the entry at `index` of `input`, remembering it in `seen` to find later duplicates.
+fn check_entry(
+    input: &Arc<Path>,
+    index: usize,
+    value: Value,
+    seen: &mut HashMap<DuplicateKey, (Arc<Path>, usize)>,
+    problems: &mut Vec<Problem>,
+) {
+    let problem = |file, kind| Problem {
+        input: input.clone(),
+        index: Some(index),
+        file,
+        kind,
+    };
+    let command = match check_fields(value) {
+        Ok(command) => command,
+        Err(kinds) => {
+            problems.extend(kinds.into_iter().map(|kind| problem(None, kind)));
+            return;
+        }
+    };
+    let entry = Entry {
+        input: input.clone(),
+        command,
+    };
+    let file = Some(entry.command.file.clone());
+    for kind in check_command(&entry.command)
+        .into_iter()
+        .chain(check_paths(&entry))
+    {
+        problems.push(problem(file.clone(), kind));
+    }
+    match seen.get(&duplicate_key(&entry)) {
+        Some((first_input, first_index)) => problems.push(problem(
+            file,
+            ProblemKind::Duplicate(first_input.clone(), *first_index),
+        )),
+        None => {
+            seen.insert(duplicate_key(&entry), (input.clone(), index));
+        }
+    }
+}
+
+/// Checks presence and types of the fields of an entry and parses it.
+fn check_fields(value: Value) -> Result<CompileCommand, Vec<ProblemKind>> {
+    let object = match &value {
+        Value::Object(object) => object,
+        _ => return Err(vec![ProblemKind::NotAnObject]),
+    };
+    let mut problems = Vec::new();
+    for field in ["directory", "file"] {
+        match object.get(field) {
+            None => problems.push(ProblemKind::MissingField(field)),
+            Some(Value::String(_)) => {}
+            Some(_) => problems.push(ProblemKind::InvalidField(field)),
+        }
+    }
+    for field in ["command", "output"] {
+        if object
+            .get(field)
+            .is_some_and(|value| !value.is_string() && !value.is_null())
+        {
+            problems.push(ProblemKind::InvalidField(field));
+        }
+    }
+    let arguments_valid = match object.get("arguments") {
+        None | Some(Value::Null) => true,
+        Some(Value::Array(arguments)) => arguments.iter().all(Value::is_string),
+        Some(_) => false,
+    };
+    if !arguments_valid {
+        problems.push(ProblemKind::InvalidField("arguments"));
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    serde_json::from_value(value).map_err(|err| vec![ProblemKind::Unreadable(err.to_string())])
+}
+
+fn check_command(command: &CompileCommand) -> Option<ProblemKind> {
+    match (&command.arguments, &command.command) {
+        (None, None) => Some(ProblemKind::NoCommand),
+        (Some(arguments), _) if arguments.is_empty() => Some(ProblemKind::NoCommand),
+        (None, Some(command)) => match shell::split(command) {
+            Ok(argv) if argv.is_empty() => Some(ProblemKind::NoCommand),
+            Ok(_) => None,
+            Err(err) => Some(ProblemKind::InvalidCommand(err)),
+        },
+        _ => None,
+    }
+}
+
+fn check_paths(entry: &Entry) -> Vec<ProblemKind> {
+    let command = &entry.command;
+    let mut problems = Vec::new();
+    let paths = [
+        ("directory", Some(&command.directory)),
+        ("file", Some(&command.file)),
+        ("output", command.output.as_ref()),
+    ];
+    for (field, path) in paths {
+        if path.is_some_and(|path| !path.is_absolute()) {
+            problems.push(ProblemKind::RelativePath(field));
+        }
+    }
+
+    // relative paths are still checked where they point to
+    let directory = absolute(input_directory(&entry.input), &command.directory);
+    if !directory.is_dir() {
+        problems.push(ProblemKind::MissingDirectory(directory.clone()));
+    }
+    let file = absolute(&directory, &command.file);
+    if !file.exists() {
+        problems.push(ProblemKind::MissingFile(file));
+    }
+    problems
+}
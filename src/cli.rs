@@ -0,0 +1,499 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use join_compile_commands_json::{
+    shell, CommandFormat, CompilerRules, DuplicatePolicy, FlagRules, Glob, PathPattern, PathStyle,
+    PrefixMapping, SortKey, COMPILE_COMMANDS_JSON_FILE_NAME, DEFAULT_SORT_KEYS, KNOWN_LAUNCHERS,
+};
+
+const USAGE: &str = "\
+Usage: join_compile_commands_json [OPTIONS] [INPUT]...
+       join_compile_commands_json check [OPTIONS] [INPUT]...
+
+Joins multiple compile_commands.json files into one.
+
+Commands:
+  check                  Don't join anything, report unreadable databases and entries with missing or invalid
+                         fields, without a command, with paths which aren't absolute or don't exist and duplicates,
+                         exit with a non-zero code if there are any (use `./check` for an INPUT called `check`)
+
+Arguments:
+  [INPUT]...             Directories to search for database files or database files to join directly
+                         [default: current directory]
+
+Options:
+  -o, --output <PATH>    Write the joined database to PATH, `-` for stdout [default: compile_commands.json]
+      --paths <STYLE>    Write `directory`, `file` and `output` paths resolved to `absolute` ones, `relative` to the
+                         output directory (including path arguments, so the database can be moved together with the
+                         tree) or `keep` them as they are in the inputs [default: absolute]
+      --map-prefix <OLD=NEW>
+                         Replace the path prefix OLD with NEW in `directory`, `file`, `output` and path arguments of
+                         the compile commands, may be repeated (the first matching one is used)
+      --headers          Add entries for headers next to sources, directly in include directories within the joined
+                         projects or included by sources, with the flags of the most relevant source (the same name
+                         or directory, or including the header)
+      --strip-launchers  Remove compiler launchers (ccache, sccache, distcc, icecc, buildcache, pump) from commands
+      --launcher <NAME>  Also remove launcher NAME from commands, may be repeated
+      --compiler <PATH>  Replace the compiler of every command with PATH, a target triple prefixing the original
+                         compiler's name (like `arm-none-eabi-gcc`) is kept as `--target`
+      --cxx-compiler <PATH>
+                         Replace C++ compilers (`g++`, `clang++`, ...) with PATH instead [default: --compiler]
+      --remove-flag <PATTERN>
+                         Remove compiler flags matching PATTERN (a glob, or `FLAG VALUE` globs for a flag followed
+                         by its value), known flags taking a separate value are removed together with it when
+                         either matches, may be repeated
+      --replace-flag <OLD=NEW>
+                         Replace compiler flags matching OLD with NEW, may be repeated
+      --add-flag <FLAG>  Append FLAG to every compile command, may be repeated
+      --format <FORMAT>  Write compile commands as `arguments` lists, as `command` strings or `keep` the form used by
+                         each input [default: keep]
+      --sort <KEYS>      Order entries by comma separated KEYS out of `input`, `file`, `directory` and `output`,
+                         `none` keeps the order in which inputs are found [default: input,file]
+      --duplicates <POLICY>
+                         Entries with the same directory, file and output are duplicates, keep `all` of them or just
+                         the `first`, `last`, `newest` (by input modification time) or `priority` one (found under
+                         the earliest listed INPUT) [default: all]
+      --name <GLOB>      Search for database files with names matching GLOB, may be repeated
+                         [default: compile_commands.json]
+      --exclude <PATTERN>
+                         Don't search directories and files matching PATTERN, may be repeated
+      --include <PATTERN>
+                         Only join databases matching PATTERN, may be repeated
+      --use-ignore-files Honor `.gitignore` and `.ignore` files in searched directories
+      --ignore-file <NAME>
+                         Honor ignore files named NAME in searched directories, may be repeated
+      --max-depth <N>    Only join databases at most N levels below their INPUT directory, directly inside is level 1
+      --min-depth <N>    Only join databases at least N levels below their INPUT directory
+  -L, --follow-symlinks  Follow symlinks to directories, each directory is still searched only once
+  -j, --jobs <N>         Search at most N directories and parse at most N databases concurrently
+                         [default: twice the number of CPUs, at least 4]
+  -k, --keep-going       Warn about inputs which can't be searched or read and join the rest
+                         [default: fail without writing any output]
+      --include-joined   Also join databases previously produced by this tool found during the search
+      --cache            Keep the parsed inputs in a cache file next to the output, only re-read inputs which
+                         changed since the last run and don't write the output at all if none did (unless `--headers`
+                         are added or `--duplicates newest` is used, which depend on more than the inputs)
+      --stream           Read and write one entry at a time instead of loading all inputs into memory first, for
+                         huge databases; entries keep the order of their inputs, so `--sort`, `--duplicates`,
+                         `--headers` and `--cache` can't be used
+  -w, --watch            Keep running and join again whenever a database under the INPUTs is created, changed or
+                         removed (Linux only)
+  -h, --help             Print help
+  -V, --version          Print version
+";
+
+/// Options which don't take a value, short ones can be bundled like `-kL`.
+const SWITCHES: &[&str] = &[
+    "-h",
+    "--help",
+    "-V",
+    "--version",
+    "--headers",
+    "--strip-launchers",
+    "--use-ignore-files",
+    "-L",
+    "--follow-symlinks",
+    "-k",
+    "--keep-going",
+    "--include-joined",
+    "--cache",
+    "--stream",
+    "-w",
+    "--watch",
+];
+
+/// Where the joined database gets written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Options of a single join run.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub output: Output,
+    pub inputs: Vec<PathBuf>,
+    pub include_joined: bool,
+    pub sort_keys: Vec<SortKey>,
+    pub keep_going: bool,
+    pub duplicates: DuplicatePolicy,
+    pub jobs: usize,
+    pub ignore_files: Vec<String>,
+    pub exclude: Vec<PathPattern>,
+    pub include: Vec<PathPattern>,
+    pub follow_symlinks: bool,
+    pub max_depth: Option<usize>,
+    pub min_depth: usize,
+    pub names: Vec<Glob>,
+    pub path_style: PathStyle,
+    pub prefix_mappings: Vec<PrefixMapping>,
+    pub headers: bool,
+    pub format: CommandFormat,
+    pub flag_rules: FlagRules,
+    pub compiler_rules: CompilerRules,
+    pub cache: bool,
+    pub stream: bool,
+    pub watch: bool,
+}
+
+/// What the binary has been asked to do.
+#[derive(Debug)]
+pub enum Command {
+    Join(Box<Options>),
+    Check(Box<Options>),
+    Help,
+    Version,
+}
+
+/// Invalid command line.
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+pub fn version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+/// Parses command line arguments (without the name of the binary).
+pub fn parse<I>(args: I) -> Result<Command, UsageError>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter().peekable();
+    let check = args.next_if(|arg| arg == "check").is_some();
+    let mut options = Options {
+        output: Output::File(PathBuf::from(COMPILE_COMMANDS_JSON_FILE_NAME)),
+        inputs: Vec::new(),
+        include_joined: false,
+        sort_keys: DEFAULT_SORT_KEYS.to_vec(),
+        keep_going: false,
+        duplicates: DuplicatePolicy::KeepAll,
+        jobs: 0,
+        ignore_files: Vec::new(),
+        exclude: Vec::new(),
+        include: Vec::new(),
+        follow_symlinks: false,
+        max_depth: None,
+        min_depth: 0,
+        names: Vec::new(),
+        path_style: PathStyle::Absolute,
+        prefix_mappings: Vec::new(),
+        headers: false,
+        format: CommandFormat::Keep,
+        flag_rules: FlagRules::default(),
+        compiler_rules: CompilerRules::default(),
+        cache: false,
+        stream: false,
+        watch: false,
+    };
+    let mut sort_given = false;
+
+    // the rest of bundled short switches, parsed as the next argument
+    let mut bundled: Option<OsString> = None;
+    while let Some(arg) = bundled.take().or_else(|| args.next()) {
+        let arg = match arg.into_string() {
+            Ok(arg) => arg,
+            // options are always valid unicode so this must be an input path
+            Err(arg) => {
+                options.inputs.push(PathBuf::from(arg));
+                continue;
+            }
+        };
+
+        // split `--name=value` and `-nvalue` forms
+        let (name, mut inline_value) = if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                None => (arg.clone(), None),
+            }
+        } else if arg.starts_with('-') && arg.len() > 2 && arg.is_char_boundary(2) {
+            (arg[..2].to_string(), Some(arg[2..].to_string()))
+        } else {
+            (arg.clone(), None)
+        };
+        if SWITCHES.contains(&name.as_str()) {
+            if let Some(rest) = inline_value.take() {
+                if name.starts_with("--") {
+                    return Err(UsageError(format!("`{}` doesn't take a value", name)));
+                }
+                bundled = Some(format!("-{}", rest).into());
+            }
+        }
+        let mut value = |name: &str| -> Result<OsString, UsageError> {
+            match inline_value.clone() {
+                Some(value) => Ok(value.into()),
+                None => args
+                    .next()
+                    .ok_or_else(|| UsageError(format!("missing value for `{}`", name))),
+            }
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" | "--output" => {
+                let path = value(&name)?;
+                options.output = if path == "-" {
+                    Output::Stdout
+                } else {
+                    Output::File(path.into())
+                };
+            }
+            "--paths" => options.path_style = parse_value(&name, value(&name)?)?,
+            "--map-prefix" => options
+                .prefix_mappings
+                .push(parse_value(&name, value(&name)?)?),
+            "--headers" => options.headers = true,
+            "--strip-launchers" => options
+                .compiler_rules
+                .strip_launchers
+                .extend(KNOWN_LAUNCHERS.iter().map(|launcher| launcher.to_string())),
+            "--launcher" => options
+                .compiler_rules
+                .strip_launchers
+                .push(parse_value(&name, value(&name)?)?),
+            "--compiler" => {
+                options.compiler_rules.compiler = Some(parse_value(&name, value(&name)?)?)
+            }
+            "--cxx-compiler" => {
+                options.compiler_rules.cxx_compiler = Some(parse_value(&name, value(&name)?)?);
+            }
+            "--remove-flag" => options
+                .flag_rules
+                .remove
+                .push(parse_value(&name, value(&name)?)?),
+            "--replace-flag" => options
+                .flag_rules
+                .replace
+                .push(parse_value(&name, value(&name)?)?),
+            "--add-flag" => {
+                let flags = value(&name)?;
+                let flags = flags
+                    .to_str()
+                    .ok_or_else(|| UsageError("invalid value for `--add-flag`".to_string()))?;
+                options.flag_rules.add.extend(
+                    shell::split(flags)
+                        .map_err(|err| UsageError(format!("`--add-flag`: {}", err)))?,
+                );
+            }
+            "--format" => options.format = parse_value(&name, value(&name)?)?,
+            "--sort" => {
+                options.sort_keys = parse_sort_keys(&value(&name)?)?;
+                sort_given = true;
+            }
+            "--duplicates" => options.duplicates = parse_value(&name, value(&name)?)?,
+            "--name" => options.names.push(parse_value(&name, value(&name)?)?),
+            "--exclude" => options.exclude.push(parse_value(&name, value(&name)?)?),
+            "--include" => options.include.push(parse_value(&name, value(&name)?)?),
+            "--use-ignore-files" => {
+                options
+                    .ignore_files
+                    .extend([".gitignore".to_string(), ".ignore".to_string()]);
+            }
+            "--ignore-file" => options.ignore_files.push(
+                value(&name)?
+                    .into_string()
+                    .map_err(|_| UsageError("invalid value for `--ignore-file`".to_string()))?,
+            ),
+            "--max-depth" => options.max_depth = Some(parse_number(&name, value(&name)?)?),
+            "--min-depth" => options.min_depth = parse_number(&name, value(&name)?)?,
+            "-L" | "--follow-symlinks" => options.follow_symlinks = true,
+            "-j" | "--jobs" => {
+                options.jobs = Some(parse_number(&name, value(&name)?)?)
+                    .filter(|&jobs| jobs > 0)
+                    .ok_or_else(|| UsageError(format!("`{}` expects a positive number", name)))?;
+            }
+            "-k" | "--keep-going" => options.keep_going = true,
+            "--include-joined" => options.include_joined = true,
+            "--cache" => options.cache = true,
+            "--stream" => options.stream = true,
+            "-w" | "--watch" => options.watch = true,
+            "--" => {
+                options.inputs.extend(args.by_ref().map(PathBuf::from));
+            }
+            _ if name.starts_with('-') && name != "-" => {
+                return Err(UsageError(format!("unknown option `{}`", arg)));
+            }
+            _ => options.inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if options.watch && check {
+        return Err(UsageError(
+            "`--watch` can't be used with `check`".to_string(),
+        ));
+    }
+    if options.watch && options.output == Output::Stdout {
+        return Err(UsageError("`--watch` needs an output file".to_string()));
+    }
+    if options.stream {
+        // all of these need every entry in memory at once
+        let conflicting = [
+            (sort_given, "--sort"),
+            (
+                options.duplicates != DuplicatePolicy::KeepAll,
+                "--duplicates",
+            ),
+            (options.headers, "--headers"),
+            (options.cache, "--cache"),
+        ];
+        if let Some((_, name)) = conflicting.iter().find(|(given, _)| *given) {
+            return Err(UsageError(format!(
+                "`--stream` can't be used with `{}`",
+                name
+            )));
+        }
+    }
+    if check {
+        Ok(Command::Check(Box::new(options)))
+    } else {
+        Ok(Command::Join(Box::new(options)))
+    }
+}
+
+fn parse_sort_keys(value: &OsString) -> Result<Vec<SortKey>, UsageError> {
+    let value = value
+        .to_str()
+        .ok_or_else(|| UsageError("invalid value for `--sort`".to_string()))?;
+    if value == "none" {
+        return Ok(Vec::new());
+    }
+    value
+        .split(',')
+        .map(|key| key.parse().map_err(UsageError))
+        .collect()
+}
+
+fn parse_number(name: &str, value: OsString) -> Result<usize, UsageError> {
+    value
+        .to_str()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| UsageError(format!("`{}` expects a number", name)))
+}
+
+fn parse_value<T>(name: &str, value: OsString) -> Result<T, UsageError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .to_str()
+        .ok_or_else(|| UsageError(format!("invalid value for `{}`", name)))?
+        .parse()
+        .map_err(|err| UsageError(format!("{}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, UsageError> {
+        parse(args.iter().map(OsString::from))
+    }
+
+    fn join_options(args: &[&str]) -> Options {
+        match parse_args(args) {
+            Ok(Command::Join(options)) => *options,
+            other => panic!("expected join options for {:?}, got {:?}", args, other),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse_args(args) {
+            Err(err) => err.to_string(),
+            Ok(command) => panic!("expected an error for {:?}, got {:?}", args, command),
+        }
+    }
+
+    #[test]
+    fn expands_bundled_switches() {
+        let options = join_options(&["-kL", "build"]);
+        assert!(options.keep_going && options.follow_symlinks);
+        assert_eq!(options.inputs, [PathBuf::from("build")]);
+
+        // the last switch of a bundle may take the next argument as its value
+        let options = join_options(&["-ko", "-", "build"]);
+        assert!(options.keep_going);
+        assert_eq!(options.output, Output::Stdout);
+        assert_eq!(options.inputs, [PathBuf::from("build")]);
+
+        assert_eq!(join_options(&["-j4"]).jobs, 4);
+        assert_eq!(error(&["-kx"]), "unknown option `-x`");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(error(&["--help=x"]), "`--help` doesn't take a value");
+        assert_eq!(
+            error(&["--keep-going=yes"]),
+            "`--keep-going` doesn't take a value"
+        );
+        assert_eq!(error(&["-j0"]), "`-j` expects a positive number");
+        assert_eq!(
+            error(&["--jobs", "0"]),
+            "`--jobs` expects a positive number"
+        );
+        assert_eq!(error(&["-o"]), "missing value for `-o`");
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let options = join_options(&["-k", "--", "-L", "--help"]);
+        assert!(options.keep_going && !options.follow_symlinks);
+        assert_eq!(
+            options.inputs,
+            [PathBuf::from("-L"), PathBuf::from("--help")]
+        );
+    }
+
+    #[test]
+    fn check_is_only_a_subcommand_first() {
+        match parse_args(&["check", "-k", "build"]) {
+            Ok(Command::Check(options)) => {
+                assert!(options.keep_going);
+                assert_eq!(options.inputs, [PathBuf::from("build")]);
+            }
+            other => panic!("expected check options, got {:?}", other),
+        }
+        assert_eq!(
+            join_options(&["build", "check"]).inputs,
+            [PathBuf::from("build"), PathBuf::from("check")]
+        );
+        assert_eq!(
+            error(&["check", "--watch"]),
+            "`--watch` can't be used with `check`"
+        );
+    }
+
+    #[test]
+    fn stream_conflicts_with_whole_database_options() {
+        assert!(join_options(&["--stream", "--format", "arguments"]).stream);
+        for (option, args) in [
+            ("--sort", &["--sort", "file"][..]),
+            ("--duplicates", &["--duplicates", "first"][..]),
+            ("--headers", &["--headers"][..]),
+            ("--cache", &["--cache"][..]),
+        ] {
+            let mut args = args.to_vec();
+            args.push("--stream");
+            assert_eq!(
+                error(&args),
+                format!("`--stream` can't be used with `{}`", option)
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::shell::{self, ShellError};
+
+/// A single entry of a [JSON Compilation Database](https://clang.llvm.org/docs/JSONCompilationDatabase.html).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompileCommand {
+    /// The working directory of the compilation.
+    pub directory: PathBuf,
+
+    /// The main translation unit source processed by this compilation step.
+    pub file: PathBuf,
+
+    /// The compile command as a single shell-escaped string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// The compile command as a list of strings (argv).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<String>>,
+
+    /// The name of the output created by this compilation step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+}
+
+impl CompileCommand {
+    /// Returns the compile command as argv, splitting `command` if there are no `arguments`.
+    ///
+    /// Entries with neither `arguments` nor `command` have an empty argv.
+    pub fn argv(&self) -> Result<Vec<String>, ShellError> {
+        match (&self.arguments, &self.command) {
+            (Some(arguments), _) => Ok(arguments.clone()),
+            (None, Some(command)) => shell::split(command),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// Replaces the compile command with `argv`, keeping the form(s) it's currently written in.
+    pub fn set_argv(&mut self, argv: Vec<String>) {
+        if self.command.is_some() {
+            self.command = Some(shell::join(&argv));
+        }
+        if self.arguments.is_some() || self.command.is_none() {
+            self.arguments = Some(argv);
+        }
+    }
+}
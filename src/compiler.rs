@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use crate::{Entry, Result};
+
+/// Compiler launchers (caching and distributing wrappers) stripped by [`CompilerRules::strip_launchers`].
+pub const KNOWN_LAUNCHERS: &[&str] =
+    &["ccache", "sccache", "distcc", "icecc", "buildcache", "pump"];
+
+/// Substitution of the compiler running each compile command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompilerRules {
+    /// Names of launchers removed from the start of each command, compared against the executable's file name.
+    pub strip_launchers: Vec<String>,
+
+    /// Driver replacing the compiler of every command, e.g. `clang`. Known launchers get replaced along with it.
+    pub compiler: Option<String>,
+
+    /// Driver replacing C++ compilers (`g++`, `c++`, `clang++`, ...), [`CompilerRules::compiler`] if not set.
+    pub cxx_compiler: Option<String>,
+}
+
+impl CompilerRules {
+    pub fn is_empty(&self) -> bool {
+        self.strip_launchers.is_empty() && self.compiler.is_none() && self.cxx_compiler.is_none()
+    }
+
+    /// Applies the rules to `argv`.
+    ///
+    /// When the compiler gets replaced and its name carries a toolchain prefix (like `arm-none-eabi-gcc`), the prefix
+    /// is passed on as `--target` so the new driver still compiles for the same target.
+    pub fn apply(&self, argv: &[String]) -> Vec<String> {
+        // replacing the compiler has to replace the actual compiler, so known launchers are skipped in that case too
+        let replacing = self.compiler.is_some() || self.cxx_compiler.is_some();
+        let mut argv = argv;
+        // launchers may be stacked, like `ccache distcc gcc`
+        while argv.len() > 1 && self.is_launcher(&argv[0], replacing) {
+            argv = &argv[1..];
+        }
+        let (compiler, flags) = match argv.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        let replacement = if is_cxx_compiler(compiler) {
+            self.cxx_compiler.as_ref().or(self.compiler.as_ref())
+        } else {
+            self.compiler.as_ref()
+        };
+        let replacement = match replacement {
+            Some(replacement) => replacement,
+            None => return argv.to_vec(),
+        };
+
+        let mut result = vec![replacement.clone()];
+        let has_target = flags
+            .iter()
+            .any(|flag| flag == "-target" || flag == "--target" || flag.starts_with("--target="));
+        if !has_target {
+            if let Some(target) = target_from_compiler(compiler) {
+                result.push(format!("--target={}", target));
+            }
+        }
+        result.extend(flags.iter().cloned());
+        result
+    }
+
+    fn is_launcher(&self, argument: &str, include_known: bool) -> bool {
+        let name = executable_name(argument);
+        self.strip_launchers
+            .iter()
+            .any(|launcher| *launcher == name)
+            || (include_known && KNOWN_LAUNCHERS.contains(&name))
+    }
+}
+
+/// File name of an executable without directories and a Windows `.exe` extension.
+pub(crate) fn executable_name(path: &str) -> &str {
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path);
+    name.strip_suffix(".exe").unwrap_or(name)
+}
+
+/// Compiler names which may be prefixed with a target triple.
+const COMPILER_NAMES: &[&str] = &["gcc", "g++", "cc", "c++", "clang", "clang++", "cpp"];
+
+/// Splits an executable name like `arm-none-eabi-gcc-12` into the triple (`arm-none-eabi`) and the compiler (`gcc`).
+fn split_compiler_name(name: &str) -> Option<(Option<&str>, &str)> {
+    // drop a version suffix like `-12` or `-12.2`
+    let name = match name.rsplit_once('-') {
+        Some((rest, version)) if version.chars().all(|c| c.is_ascii_digit() || c == '.') => rest,
+        _ => name,
+    };
+    COMPILER_NAMES.iter().find_map(|compiler| {
+        if name == *compiler {
+            return Some((None, *compiler));
+        }
+        let triple = name.strip_suffix(compiler)?.strip_suffix('-')?;
+        Some((Some(triple), *compiler))
+    })
+}
+
+fn is_cxx_compiler(path: &str) -> bool {
+    split_compiler_name(executable_name(path)).is_some_and(|(_, compiler)| compiler.ends_with("++"))
+}
+
+/// Returns the target triple from a cross compiler's name, e.g. `arm-none-eabi` for `/opt/bin/arm-none-eabi-gcc`.
+///
+/// Some toolchains are prefixed with just the architecture, like `avr-gcc`, which Clang accepts as a target too.
+pub fn target_from_compiler(path: &str) -> Option<String> {
+    let (triple, _) = split_compiler_name(executable_name(path))?;
+    triple.map(str::to_string)
+}
+
+/// Strips launchers and replaces compilers of all `entries` according to `rules`.
+pub fn substitute_compiler(entries: &mut [Entry], rules: &CompilerRules) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    for entry in entries {
+        if entry.command.arguments.is_none() && entry.command.command.is_none() {
+            continue;
+        }
+        let argv = entry.argv()?;
+        let command = &mut entry.command;
+        let substituted = rules.apply(&argv);
+        if substituted != argv {
+            command.set_argv(substituted);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    fn replacing(compiler: Option<&str>, cxx_compiler: Option<&str>) -> CompilerRules {
+        CompilerRules {
+            strip_launchers: Vec::new(),
+            compiler: compiler.map(str::to_string),
+            cxx_compiler: cxx_compiler.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn splits_triples_and_versions() {
+        assert_eq!(
+            split_compiler_name("arm-none-eabi-gcc-12"),
+            Some((Some("arm-none-eabi"), "gcc"))
+        );
+        assert_eq!(split_compiler_name("clang++"), Some((None, "clang++")));
+        assert_eq!(split_compiler_name("c++"), Some((None, "c++")));
+        assert_eq!(split_compiler_name("gcc-12.2"), Some((None, "gcc")));
+        assert_eq!(split_compiler_name("avr-gcc"), Some((Some("avr"), "gcc")));
+        assert_eq!(split_compiler_name("clang-cl"), None);
+        assert_eq!(split_compiler_name("cl"), None);
+    }
+
+    #[test]
+    fn targets_come_from_compiler_names() {
+        assert_eq!(
+            target_from_compiler("/opt/bin/arm-none-eabi-gcc-12").as_deref(),
+            Some("arm-none-eabi")
+        );
+        assert_eq!(
+            target_from_compiler("x86_64-w64-mingw32-g++.exe").as_deref(),
+            Some("x86_64-w64-mingw32")
+        );
+        assert_eq!(target_from_compiler("avr-gcc").as_deref(), Some("avr"));
+        assert_eq!(target_from_compiler("/usr/bin/clang++"), None);
+        assert_eq!(target_from_compiler("clang-cl"), None);
+    }
+
+    #[test]
+    fn replaces_compilers_passing_the_target_on() {
+        let rules = replacing(Some("clang"), None);
+        assert_eq!(
+            rules.apply(&argv(&["arm-none-eabi-gcc-12", "-c", "a.c"])),
+            argv(&["clang", "--target=arm-none-eabi", "-c", "a.c"])
+        );
+        // without a triple or with an explicit target nothing is added
+        assert_eq!(
+            rules.apply(&argv(&["gcc", "-c", "a.c"])),
+            argv(&["clang", "-c", "a.c"])
+        );
+        assert_eq!(
+            rules.apply(&argv(&["avr-gcc", "--target=avr", "-c", "a.c"])),
+            argv(&["clang", "--target=avr", "-c", "a.c"])
+        );
+        assert_eq!(
+            rules.apply(&argv(&["avr-gcc", "-target", "avr", "-c", "a.c"])),
+            argv(&["clang", "-target", "avr", "-c", "a.c"])
+        );
+    }
+
+    #[test]
+    fn cxx_compiler_falls_back_to_compiler() {
+        let rules = replacing(Some("clang"), None);
+        assert_eq!(
+            rules.apply(&argv(&["c++", "-c", "a.cpp"])),
+            argv(&["clang", "-c", "a.cpp"])
+        );
+
+        let rules = replacing(Some("clang"), Some("clang++"));
+        assert_eq!(
+            rules.apply(&argv(&["g++", "-c", "a.cpp"])),
+            argv(&["clang++", "-c", "a.cpp"])
+        );
+        assert_eq!(
+            rules.apply(&argv(&["cc", "-c", "a.c"])),
+            argv(&["clang", "-c", "a.c"])
+        );
+
+        // only C++ compilers are replaced then
+        let rules = replacing(None, Some("clang++"));
+        assert_eq!(
+            rules.apply(&argv(&["clang-cl", "/c", "a.c"])),
+            argv(&["clang-cl", "/c", "a.c"])
+        );
+        assert_eq!(
+            rules.apply(&argv(&["clang++", "-c", "a.cpp"])),
+            argv(&["clang++", "-c", "a.cpp"])
+        );
+    }
+
+    #[test]
+    fn strips_stacked_launchers() {
+        let command = argv(&["/usr/bin/ccache", "distcc", "g++", "-c", "a.cpp"]);
+        let rules = CompilerRules {
+            strip_launchers: argv(&["ccache", "distcc"]),
+            ..CompilerRules::default()
+        };
+        assert_eq!(rules.apply(&command), argv(&["g++", "-c", "a.cpp"]));
+
+        // known launchers go along with a replaced compiler
+        assert_eq!(
+            replacing(None, Some("clang++")).apply(&command),
+            argv(&["clang++", "-c", "a.cpp"])
+        );
+
+        // but aren't stripped otherwise
+        let rules = CompilerRules {
+            strip_launchers: argv(&["ccache"]),
+            ..CompilerRules::default()
+        };
+        assert_eq!(
+            rules.apply(&command),
+            argv(&["distcc", "g++", "-c", "a.cpp"])
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::{Entry as MapEntry, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::paths::{input_directory, normalize};
+use crate::{Entry, Result};
+
+/// Which entry to keep when several entries describe the same compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Keep all entries, duplicates included.
+    KeepAll,
+    /// Keep the entry which comes first in the joined database.
+    KeepFirst,
+    /// Keep the entry which comes last in the joined database.
+    KeepLast,
+    /// Keep the entry from the most recently modified input, ties are resolved like [`DuplicatePolicy::KeepFirst`].
+    KeepNewest,
+    /// Keep the entry found under the earliest listed root, ties are resolved like [`DuplicatePolicy::KeepFirst`].
+    KeepPriority,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(DuplicatePolicy::KeepAll),
+            "first" => Ok(DuplicatePolicy::KeepFirst),
+            "last" => Ok(DuplicatePolicy::KeepLast),
+            "newest" => Ok(DuplicatePolicy::KeepNewest),
+            "priority" => Ok(DuplicatePolicy::KeepPriority),
+            _ => Err(format!(
+                "unknown duplicate policy `{}` (expected `all`, `first`, `last`, `newest` or `priority`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DuplicatePolicy::KeepAll => "all",
+            DuplicatePolicy::KeepFirst => "first",
+            DuplicatePolicy::KeepLast => "last",
+            DuplicatePolicy::KeepNewest => "newest",
+            DuplicatePolicy::KeepPriority => "priority",
+        })
+    }
+}
+
+/// An entry dropped as a duplicate of another one.
+#[derive(Debug, Clone)]
+pub struct DroppedDuplicate {
+    /// The dropped entry.
+    pub dropped: Entry,
+
+    /// Input of the entry which has been kept instead.
+    pub kept_input: Arc<Path>,
+}
+
+impl fmt::Display for DroppedDuplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dropped duplicate entry for {} from {} (kept the one from {})",
+            self.dropped.command.file.display(),
+            self.dropped.input.display(),
+            self.kept_input.display()
+        )
+    }
+}
+
+/// Identity of a compilation: normalized directory, file and output.
+pub(crate) type DuplicateKey = (PathBuf, PathBuf, Option<PathBuf>);
+
+pub(crate) fn duplicate_key(entry: &Entry) -> DuplicateKey {
+    let command = &entry.command;
+    // relative directories are relative to the database, everything else is relative to the directory
+    let mut directory = input_directory(&entry.input).join(&command.directory);
+    if directory.is_relative() {
+        // inputs found under relative roots (like `.`) have relative paths themselves
+        if let Ok(current) = std::env::current_dir() {
+            directory = current.join(directory);
+        }
+    }
+    let directory = normalize(&directory);
+    let file = normalize(&directory.join(&command.file));
+    let output = command
+        .output
+        .as_ref()
+        .map(|output| normalize(&directory.join(output)));
+    (directory, file, output)
+}
+
+/// How strongly an entry should be kept, lower wins: root priority first, then input modification time.
+type Rank = (usize, Reverse<SystemTime>);
+
+/// Removes entries describing the same compilation as another entry according to `policy`.
+///
+/// Two entries are duplicates if their normalized `directory`, `file` and `output` are equal. `roots` are the search
+/// roots in order of priority, used by [`DuplicatePolicy::KeepPriority`]. The kept entries stay in place, the dropped
+/// ones are returned.
+pub fn dedup_entries(
+    entries: &mut Vec<Entry>,
+    policy: DuplicatePolicy,
+    roots: &[PathBuf],
+) -> Result<Vec<DroppedDuplicate>> {
+    if policy == DuplicatePolicy::KeepAll {
+        return Ok(Vec::new());
+    }
+
+    let mut modified: HashMap<Arc<Path>, SystemTime> = HashMap::new();
+    let mut ranks: Vec<Rank> = Vec::with_capacity(entries.len());
+    for entry in entries.iter() {
+        let priority = match policy {
+            DuplicatePolicy::KeepPriority => roots
+                .iter()
+                .position(|root| entry.input.starts_with(root))
+                .unwrap_or(roots.len()),
+            _ => 0,
+        };
+        let time = match policy {
+            DuplicatePolicy::KeepNewest => match modified.entry(entry.input.clone()) {
+                MapEntry::Occupied(occupied) => *occupied.get(),
+                MapEntry::Vacant(vacant) => *vacant.insert(
+                    fs::metadata(&entry.input)
+                        .and_then(|metadata| metadata.modified())
+                        .map_err(|err| format!("{}: {}", entry.input.display(), err))?,
+                ),
+            },
+            _ => SystemTime::UNIX_EPOCH,
+        };
+        ranks.push((priority, Reverse(time)));
+    }
+
+    // find the index of the winning entry for every key
+    let keys: Vec<DuplicateKey> = entries.iter().map(duplicate_key).collect();
+    let mut winners: HashMap<&DuplicateKey, usize> = HashMap::new();
+    for (index, key) in keys.iter().enumerate() {
+        winners
+            .entry(key)
+            .and_modify(|winner| {
+                let replace = match policy {
+                    DuplicatePolicy::KeepLast => true,
+                    DuplicatePolicy::KeepNewest | DuplicatePolicy::KeepPriority => {
+                        ranks[index] < ranks[*winner]
+                    }
+                    _ => false,
+                };
+                if replace {
+                    *winner = index;
+                }
+            })
+            .or_insert(index);
+    }
+    let winners: Vec<usize> = keys.iter().map(|key| winners[key]).collect();
+
+    let mut dropped = Vec::new();
+    let kept_inputs: Vec<Arc<Path>> = winners
+        .iter()
+        .map(|&winner| entries[winner].input.clone())
+        .collect();
+    let mut index = 0;
+    entries.retain(|entry| {
+        let keep = winners[index] == index;
+        if !keep {
+            dropped.push(DroppedDuplicate {
+                dropped: entry.clone(),
+                kept_input: kept_inputs[index].clone(),
+            });
+        }
+        index += 1;
+        keep
+    });
+    Ok(dropped)
+}
@@ -0,0 +1,536 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::glob::Glob;
+use crate::ignore::{matched_patterns, relative_path, IgnoreRules, IgnoreStack, PathPattern};
+use crate::marker;
+
+/// Name of the compilation database files searched for unless [`DiscoverOptions::names`] says otherwise.
+pub const COMPILE_COMMANDS_JSON_FILE_NAME: &str = "compile_commands.json";
+
+/// Options controlling which files [`discover_with`] finds.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoverOptions {
+    /// Files never reported by the search, typically the output of the current run.
+    ///
+    /// Found databases are skipped if they are at one of these paths or are symlinks to one. A symlink given here
+    /// only excludes itself, not the database it points to.
+    pub exclude_paths: Vec<PathBuf>,
+
+    /// Report databases previously produced by this tool too (they are skipped by default).
+    pub include_joined: bool,
+
+    /// Maximum number of directories searched concurrently, `0` picks [`default_jobs`].
+    pub jobs: usize,
+
+    /// Names of ignore files (like `.gitignore`) honored in every searched directory.
+    pub ignore_files: Vec<String>,
+
+    /// Directories and files not searched, matched relative to the root they were found under.
+    pub exclude: Vec<PathPattern>,
+
+    /// If not empty only databases matching any of these are reported, matched relative to their root.
+    pub include: Vec<PathPattern>,
+
+    /// Follow symlinks to directories, every directory is still searched at most once.
+    pub follow_symlinks: bool,
+
+    /// Only report databases at most this deep below their root, a database directly in a root is at depth 1.
+    pub max_depth: Option<usize>,
+
+    /// Only report databases at least this deep below their root, a database directly in a root is at depth 1.
+    pub min_depth: usize,
+
+    /// Patterns of database file names searched for, [`COMPILE_COMMANDS_JSON_FILE_NAME`] if empty.
+    pub names: Vec<Glob>,
+}
+
+impl DiscoverOptions {
+    /// Checks whether a file called `name` is a database searched for.
+    pub(crate) fn is_database_name(&self, name: &OsStr) -> bool {
+        if self.names.is_empty() {
+            return name == COMPILE_COMMANDS_JSON_FILE_NAME;
+        }
+        let name = name.to_string_lossy();
+        self.names.iter().any(|pattern| pattern.is_match(&name))
+    }
+
+    /// Checks whether the search descends into the directory at `path`, `depth` levels below `root`.
+    ///
+    /// `ignores` are the rules of ignore files found in the parents of `path`.
+    pub(crate) fn is_searched_dir(
+        &self,
+        root: &Path,
+        ignores: &Option<Arc<IgnoreStack>>,
+        path: &Path,
+        depth: usize,
+    ) -> bool {
+        // databases in deeper directories would be too deep already
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+            && !self.is_excluded(root, ignores, path, true)
+    }
+
+    /// Checks whether the search reports the file called `name` at `path`, `depth` levels below `root`.
+    ///
+    /// `ignores` are the rules of ignore files found in the parents of `path`.
+    pub(crate) fn is_reported_database(
+        &self,
+        root: &Path,
+        ignores: &Option<Arc<IgnoreStack>>,
+        path: &Path,
+        name: &OsStr,
+        depth: usize,
+    ) -> bool {
+        if depth < self.min_depth
+            || self.max_depth.is_some_and(|max_depth| depth > max_depth)
+            || !self.is_database_name(name)
+            || self.is_excluded(root, ignores, path, false)
+        {
+            return false;
+        }
+        if self.include.is_empty() {
+            return true;
+        }
+        let relative = relative_path(root, path).unwrap_or_default();
+        matched_patterns(&self.include, &relative, false) == Some(true)
+    }
+
+    /// Checks exclude patterns and ignore files for `path` found under `root`.
+    fn is_excluded(
+        &self,
+        root: &Path,
+        ignores: &Option<Arc<IgnoreStack>>,
+        path: &Path,
+        is_dir: bool,
+    ) -> bool {
+        if !self.exclude.is_empty() {
+            let relative = relative_path(root, path).unwrap_or_default();
+            if matched_patterns(&self.exclude, &relative, is_dir) == Some(true) {
+                return true;
+            }
+        }
+        IgnoreStack::is_ignored(ignores, path, is_dir)
+    }
+}
+
+/// Failure to search a single path.
+///
+/// The search carries on with other paths, so a single failure doesn't stop the whole discovery.
+#[derive(Debug)]
+pub struct DiscoverError {
+    /// The path which couldn't be searched.
+    pub path: PathBuf,
+
+    /// What went wrong.
+    pub source: io::Error,
+}
+
+impl fmt::Display for DiscoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for DiscoverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A compilation database found by the search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    /// Path of the database as found, under one of the roots.
+    pub path: PathBuf,
+
+    /// Canonical path of the database if it has been reached through a symlink.
+    pub resolved: Option<PathBuf>,
+}
+
+/// Item sent over the channel returned by [`discover`] and [`discover_with`].
+pub type Discovered = Result<Found, DiscoverError>;
+
+/// Recursively searches all `roots` for compilation database files with default options.
+///
+/// See [`discover_with`].
+pub fn discover<I, P>(roots: I) -> mpsc::Receiver<Discovered>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    discover_with(roots, DiscoverOptions::default())
+}
+
+/// Recursively searches all `roots` for compilation database files.
+///
+/// Roots which are files rather than directories are passed through as they are, regardless of their name and
+/// `options`. The search runs in a pool of background tasks, so this has to be called from within a tokio runtime.
+/// Found paths are sent over the returned channel which gets closed once the whole search finishes. Paths which
+/// couldn't be searched are reported over the same channel as errors.
+pub fn discover_with<I, P>(roots: I, mut options: DiscoverOptions) -> mpsc::Receiver<Discovered>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    // excluded paths are compared against canonical paths of found files
+    options.exclude_paths = options
+        .exclude_paths
+        .iter()
+        .map(|path| canonicalize_location(path))
+        .collect();
+    let jobs = if options.jobs == 0 {
+        default_jobs()
+    } else {
+        options.jobs
+    };
+    let roots: Vec<PathBuf> = roots
+        .into_iter()
+        .map(|root| root.as_ref().to_path_buf())
+        .collect();
+
+    // create channel to pass the compile_command.json paths from search workers back to the caller
+    let (results_channel, rx) = mpsc::channel(32);
+    let (queue, queue_rx) = mpsc::unbounded_channel();
+    let walker = Arc::new(Walker {
+        options,
+        jobs,
+        queue,
+        // the token of the task below, held until all roots are queued
+        pending: AtomicUsize::new(1),
+        cancelled: AtomicBool::new(false),
+        visited: std::sync::Mutex::new(HashSet::new()),
+        results_channel,
+    });
+    let queue_rx = Arc::new(Mutex::new(queue_rx));
+    for _ in 0..jobs {
+        tokio::spawn(run_worker(walker.clone(), queue_rx.clone()));
+    }
+
+    tokio::spawn(async move {
+        for root in roots {
+            match tokio::fs::metadata(&root).await {
+                Ok(metadata) if metadata.is_dir() => {
+                    if walker.first_visit(&root, &metadata) {
+                        walker.enqueue(PendingDir {
+                            root: Arc::from(root.as_path()),
+                            path: root,
+                            ignores: None,
+                            via_symlink: false,
+                            depth: 0,
+                        });
+                    }
+                }
+                Ok(_) => {
+                    // explicitly requested file -> send it over the channel as is
+                    walker
+                        .report(Ok(Found {
+                            path: root,
+                            resolved: None,
+                        }))
+                        .await;
+                }
+                Err(source) => {
+                    walker
+                        .report(Err(DiscoverError { path: root, source }))
+                        .await;
+                }
+            }
+        }
+        walker.finish_one();
+    });
+    // the walker holds the only sender, so the channel closes when all workers finish
+    rx
+}
+
+/// Number of directories searched concurrently unless set in [`DiscoverOptions::jobs`].
+pub fn default_jobs() -> usize {
+    // searching is mostly waiting for the filesystem, so use a few more workers than there are cores
+    std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1)
+        .saturating_mul(2)
+        .max(4)
+}
+
+/// Canonicalizes the directory containing `path`, so a symlink at `path` itself isn't resolved.
+///
+/// This identifies the location of a file even if it doesn't exist yet.
+pub(crate) fn canonicalize_location(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            parent
+                .canonicalize()
+                .map(|parent| parent.join(name))
+                .unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Directory waiting to be searched.
+struct PendingDir {
+    path: PathBuf,
+    /// The search root this directory has been found under.
+    root: Arc<Path>,
+    /// Rules of ignore files found in this directory's parents.
+    ignores: Option<Arc<IgnoreStack>>,
+    /// Whether a symlink has been followed on the way from the root.
+    via_symlink: bool,
+    /// Number of directories between the root and this one, the root itself is at depth 0.
+    depth: usize,
+}
+
+/// Identity of a directory regardless of the path it's reached by.
+#[cfg(unix)]
+pub(crate) type DirId = (u64, u64);
+
+#[cfg(not(unix))]
+pub(crate) type DirId = PathBuf;
+
+#[cfg(unix)]
+pub(crate) fn dir_id(_path: &Path, metadata: &std::fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn dir_id(path: &Path, _metadata: &std::fs::Metadata) -> Option<DirId> {
+    path.canonicalize().ok()
+}
+
+/// State shared by all search workers.
+struct Walker {
+    options: DiscoverOptions,
+    jobs: usize,
+    /// Directories to search, `None` tells a worker to stop.
+    queue: mpsc::UnboundedSender<Option<PendingDir>>,
+    /// Number of queued directories plus the ones being searched right now.
+    pending: AtomicUsize,
+    /// Set once nobody listens to the results anymore.
+    cancelled: AtomicBool,
+    /// Directories already queued, only tracked when following symlinks.
+    visited: std::sync::Mutex<HashSet<DirId>>,
+    results_channel: mpsc::Sender<Discovered>,
+}
+
+impl Walker {
+    fn enqueue(&self, dir: PendingDir) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        // workers only stop once nothing is pending, so the queue is still open
+        let _ = self.queue.send(Some(dir));
+    }
+
+    /// Records a visit of the directory at `path`, returns `false` if it has been visited already.
+    ///
+    /// Without following symlinks every directory is reachable by a single path only, so nothing is tracked.
+    fn first_visit(&self, path: &Path, metadata: &std::fs::Metadata) -> bool {
+        if !self.options.follow_symlinks {
+            return true;
+        }
+        match dir_id(path, metadata) {
+            Some(id) => self.visited.lock().unwrap().insert(id),
+            None => true,
+        }
+    }
+
+    /// Marks a pending directory as done, stopping all workers when it was the last one.
+    fn finish_one(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            for _ in 0..self.jobs {
+                let _ = self.queue.send(None);
+            }
+        }
+    }
+
+    /// Sends a result to the caller, returns `false` if nobody listens anymore.
+    async fn report(&self, discovered: Discovered) -> bool {
+        if self.results_channel.send(discovered).await.is_err() {
+            self.cancelled.store(true, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Extends ignore rules of `dir`'s parents with ignore files found in `dir`.
+    async fn read_ignore_files(&self, dir: &PendingDir) -> Option<Arc<IgnoreStack>> {
+        let mut ignores = dir.ignores.clone();
+        for name in &self.options.ignore_files {
+            let path = dir.path.join(name);
+            match tokio::fs::read_to_string(&path).await {
+                Ok(contents) => {
+                    ignores =
+                        IgnoreStack::push(ignores, IgnoreRules::parse(dir.path.clone(), &contents));
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(source) => {
+                    // search the directory anyway, just without these rules
+                    self.report(Err(DiscoverError { path, source })).await;
+                }
+            }
+        }
+        ignores
+    }
+
+    /// Searches a single directory queueing its subdirectories.
+    ///
+    /// Failures of the directory itself are returned, failures of its entries are reported right away.
+    async fn find_compile_commands_files(&self, dir: &PendingDir) -> io::Result<()> {
+        let mut dir_contents = tokio::fs::read_dir(&dir.path).await?;
+        let ignores = self.read_ignore_files(dir).await;
+        // entries of this directory are one level deeper than the directory itself
+        let depth = dir.depth + 1;
+        while let Some(entry) = dir_contents.next_entry().await? {
+            let file_type = match entry.file_type().await {
+                Ok(file_type) => file_type,
+                Err(source) => {
+                    let error = DiscoverError {
+                        path: entry.path(),
+                        source,
+                    };
+                    if !self.report(Err(error)).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let is_symlink = file_type.is_symlink();
+            let (is_dir, metadata) = if is_symlink && self.options.follow_symlinks {
+                match tokio::fs::metadata(&path).await {
+                    Ok(metadata) => (metadata.is_dir(), Some(metadata)),
+                    // dangling symlinks are common in build trees, just skip them
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(source) => {
+                        if !self.report(Err(DiscoverError { path, source })).await {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                (file_type.is_dir(), None)
+            };
+            if is_dir {
+                if !self
+                    .options
+                    .is_searched_dir(&dir.root, &ignores, &path, depth)
+                {
+                    continue;
+                }
+                if self.options.follow_symlinks {
+                    let metadata = match metadata {
+                        Some(metadata) => metadata,
+                        None => match entry.metadata().await {
+                            Ok(metadata) => metadata,
+                            Err(source) => {
+                                if !self.report(Err(DiscoverError { path, source })).await {
+                                    break;
+                                }
+                                continue;
+                            }
+                        },
+                    };
+                    if !self.first_visit(&path, &metadata) {
+                        // a cycle or another path to an already searched directory
+                        continue;
+                    }
+                }
+                // queue the subdir for searching
+                self.enqueue(PendingDir {
+                    path,
+                    root: dir.root.clone(),
+                    ignores: ignores.clone(),
+                    via_symlink: dir.via_symlink || is_symlink,
+                    depth,
+                });
+            } else if self.options.is_reported_database(
+                &dir.root,
+                &ignores,
+                &path,
+                &entry.file_name(),
+                depth,
+            ) && !is_skipped(&path, &self.options).await
+            {
+                let resolved = if dir.via_symlink || is_symlink {
+                    tokio::fs::canonicalize(&path).await.ok()
+                } else {
+                    None
+                };
+                // database file found -> send it over the channel
+                if !self.report(Ok(Found { path, resolved })).await {
+                    // nobody is interested in the results anymore
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Searches queued directories until told to stop.
+async fn run_worker(
+    walker: Arc<Walker>,
+    queue: Arc<Mutex<mpsc::UnboundedReceiver<Option<PendingDir>>>>,
+) {
+    loop {
+        let next = queue.lock().await.recv().await;
+        let dir = match next {
+            Some(Some(dir)) => dir,
+            _ => break,
+        };
+        if !walker.is_cancelled() {
+            if let Err(source) = walker.find_compile_commands_files(&dir).await {
+                // report the failure and let the other searches carry on
+                walker
+                    .report(Err(DiscoverError {
+                        path: dir.path,
+                        source,
+                    }))
+                    .await;
+            }
+        }
+        walker.finish_one();
+    }
+}
+
+/// Checks whether a found database should not be reported.
+async fn is_skipped(path: &Path, options: &DiscoverOptions) -> bool {
+    if !options.exclude_paths.is_empty() {
+        // a symlink to an excluded path is skipped, but an excluded symlink doesn't take its target along
+        let location = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => tokio::fs::canonicalize(parent)
+                .await
+                .ok()
+                .map(|parent| parent.join(name)),
+            _ => None,
+        };
+        let canonical = tokio::fs::canonicalize(path).await.ok();
+        if [location, canonical]
+            .iter()
+            .flatten()
+            .any(|path| options.exclude_paths.contains(path))
+        {
+            return true;
+        }
+    }
+    !options.include_joined
+        && tokio::fs::metadata(marker::joined_marker_path(path))
+            .await
+            .is_ok()
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{load, CompileCommand, Result};
+
+/// A compile command together with the database file it has been loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path of the database file the command has been loaded from.
+    pub input: Arc<Path>,
+
+    /// The compile command itself.
+    pub command: CompileCommand,
+}
+
+impl Entry {
+    /// Returns the compile command as argv, see [`CompileCommand::argv`].
+    ///
+    /// Errors mention the input and the file of the entry.
+    pub fn argv(&self) -> Result<Vec<String>> {
+        self.command.argv().map_err(|err| {
+            format!(
+                "{}: {}: {}",
+                self.input.display(),
+                self.command.file.display(),
+                err
+            )
+            .into()
+        })
+    }
+}
+
+/// Reads and parses a single compilation database file keeping track of where each entry came from.
+pub fn load_entries<P>(path: P) -> Result<Vec<Entry>>
+where
+    P: AsRef<Path>,
+{
+    let input: Arc<Path> = Arc::from(path.as_ref());
+    Ok(load(&input)?
+        .into_iter()
+        .map(|command| Entry {
+            input: input.clone(),
+            command,
+        })
+        .collect())
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::glob::Glob;
+use crate::{shell, Entry, Result};
+
+/// Compiler flags (GCC, Clang and MSVC style) which take their value as the next argument.
+const SEPARATE_VALUE_FLAGS: &[&str] = &[
+    "-o",
+    "-x",
+    "-I",
+    "-D",
+    "-U",
+    "-L",
+    "-F",
+    "-B",
+    "-l",
+    "-include",
+    "-imacros",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-iprefix",
+    "-iwithprefix",
+    "-iwithprefixbefore",
+    "-isysroot",
+    "-ivfsoverlay",
+    "-iframework",
+    "-imultilib",
+    "-MF",
+    "-MT",
+    "-MQ",
+    "-MJ",
+    "-arch",
+    "-target",
+    "-gcc-toolchain",
+    "-Xclang",
+    "-Xpreprocessor",
+    "-Xassembler",
+    "-Xlinker",
+    "-Xanalyzer",
+    "-Xarch_host",
+    "-Xarch_device",
+    "-Xcuda-fatbinary",
+    "-Xcuda-ptxas",
+    "-Xopenmp-target",
+    "-mllvm",
+    "-aux-info",
+    "-dumpbase",
+    "-dumpdir",
+    "-main-file-name",
+    "--param",
+    "--sysroot",
+    "--include-directory",
+    "--include",
+    "--output",
+    "--language",
+    "--target",
+    "--gcc-toolchain",
+    "/Fo",
+    "/Fe",
+];
+
+/// Checks whether `flag` takes its value as the next argument.
+pub(crate) fn takes_separate_value(flag: &str) -> bool {
+    SEPARATE_VALUE_FLAGS.contains(&flag)
+}
+
+/// Pattern of compiler flags to remove.
+///
+/// A single glob like `-fno-canonical-*` matches a single argument. Known flags taking a separate value (like
+/// `-include file.h`) are removed together with their value, and so are flags whose value matches (a lone
+/// `-Xclang` would apply to whatever follows it). Two globs separated by whitespace, like `-Xclang -fcolor-*`, match a
+/// flag followed by its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagPattern {
+    flag: Glob,
+    value: Option<Glob>,
+}
+
+impl FromStr for FlagPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let words =
+            shell::split(s).map_err(|err| format!("invalid flag pattern `{}`: {}", s, err))?;
+        let glob = |word: &String| Glob::new(word).map_err(|err| err.to_string());
+        match words.as_slice() {
+            [flag] => Ok(FlagPattern {
+                flag: glob(flag)?,
+                value: None,
+            }),
+            [flag, value] => Ok(FlagPattern {
+                flag: glob(flag)?,
+                value: Some(glob(value)?),
+            }),
+            _ => Err(format!(
+                "invalid flag pattern `{}` (expected `FLAG` or `FLAG VALUE`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FlagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} {}", self.flag, value),
+            None => write!(f, "{}", self.flag),
+        }
+    }
+}
+
+impl FlagPattern {
+    /// Returns how many arguments at the start of `argv` the pattern matches, `0` if it doesn't match.
+    fn matched_len(&self, argv: &[String]) -> usize {
+        let flag = match argv.first() {
+            Some(flag) if self.flag.is_match(flag) => flag,
+            _ => return 0,
+        };
+        match &self.value {
+            Some(value) => match argv.get(1) {
+                Some(next) if value.is_match(next) => 2,
+                _ => 0,
+            },
+            None if takes_separate_value(flag) => 2.min(argv.len()),
+            None => 1,
+        }
+    }
+}
+
+/// Rule replacing compiler flags, written as `OLD=NEW`.
+///
+/// `OLD` is a glob matching a single argument, `NEW` is split into arguments replacing it. As flags often contain
+/// `=` themselves, the rule is split at the first `=` followed by `-`, or at the first `=` if there's no such one, so
+/// `-std=gnu89=-std=c89` replaces `-std=gnu89` with `-std=c89`. A separate value of the replaced flag is kept, and
+/// such values are never replaced on their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagReplacement {
+    from: Glob,
+    to: Vec<String>,
+}
+
+impl FromStr for FlagReplacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let split = s
+            .match_indices("=-")
+            .next()
+            .map(|(index, _)| index)
+            .or_else(|| s.find('='))
+            .ok_or_else(|| format!("invalid flag replacement `{}` (expected `OLD=NEW`)", s))?;
+        let (from, to) = (&s[..split], &s[split + 1..]);
+        Ok(FlagReplacement {
+            from: Glob::new(from).map_err(|err| err.to_string())?,
+            to: shell::split(to)
+                .map_err(|err| format!("invalid flag replacement `{}`: {}", s, err))?,
+        })
+    }
+}
+
+impl fmt::Display for FlagReplacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.from, shell::join(&self.to))
+    }
+}
+
+/// Transformations of the compiler flags of every entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagRules {
+    /// Flags to remove.
+    pub remove: Vec<FlagPattern>,
+
+    /// Flags to replace, the first matching replacement is used.
+    pub replace: Vec<FlagReplacement>,
+
+    /// Flags appended to every command.
+    pub add: Vec<String>,
+}
+
+impl FlagRules {
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.replace.is_empty() && self.add.is_empty()
+    }
+
+    /// Applies the rules to `argv`, the compiler itself (the first argument) is never touched.
+    pub fn apply(&self, argv: &[String]) -> Vec<String> {
+        let (compiler, flags) = match argv.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+        let mut result = vec![compiler.clone()];
+        let mut i = 0;
+        while i < flags.len() {
+            // a flag with its separate value is kept or removed as a whole
+            let len = if takes_separate_value(&flags[i]) {
+                2.min(flags.len() - i)
+            } else {
+                1
+            };
+            let removed = self
+                .remove
+                .iter()
+                .map(|pattern| match pattern.matched_len(&flags[i..]) {
+                    0 if len == 2 && pattern.matched_len(&flags[i + 1..i + 2]) > 0 => len,
+                    0 => 0,
+                    matched => matched.max(len),
+                })
+                .max()
+                .unwrap_or(0);
+            if removed > 0 {
+                i += removed;
+                continue;
+            }
+            match self
+                .replace
+                .iter()
+                .find(|replacement| replacement.from.is_match(&flags[i]))
+            {
+                Some(replacement) => result.extend(replacement.to.iter().cloned()),
+                None => result.push(flags[i].clone()),
+            }
+            result.extend(flags[i + 1..i + len].iter().cloned());
+            i += len;
+        }
+        result.extend(self.add.iter().cloned());
+        result
+    }
+}
+
+/// Removes, replaces and adds compiler flags of all `entries` according to `rules`.
+///
+/// Flags are removed first, then replaced, then added.
+pub fn filter_flags(entries: &mut [Entry], rules: &FlagRules) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    for entry in entries {
+        if entry.command.arguments.is_none() && entry.command.command.is_none() {
+            continue;
+        }
+        let argv = entry.argv()?;
+        let command = &mut entry.command;
+        let filtered = rules.apply(&argv);
+        if filtered != argv {
+            command.set_argv(filtered);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    fn rules(remove: &[&str], replace: &[&str], add: &[&str]) -> FlagRules {
+        FlagRules {
+            remove: remove
+                .iter()
+                .map(|pattern| pattern.parse().unwrap())
+                .collect(),
+            replace: replace.iter().map(|rule| rule.parse().unwrap()).collect(),
+            add: argv(add),
+        }
+    }
+
+    #[test]
+    fn replacement_splits_before_the_new_flag() {
+        let replacement: FlagReplacement = "-std=gnu89=-std=c89".parse().unwrap();
+        assert!(replacement.from.is_match("-std=gnu89"));
+        assert_eq!(replacement.to, ["-std=c89"]);
+
+        let replacement: FlagReplacement = "-O*=-O0 -g".parse().unwrap();
+        assert!(replacement.from.is_match("-O2"));
+        assert_eq!(replacement.to, ["-O0", "-g"]);
+
+        // no `=-`, so the first `=` separates OLD and NEW
+        let replacement: FlagReplacement = "-Werror=".parse().unwrap();
+        assert!(replacement.from.is_match("-Werror"));
+        assert!(replacement.to.is_empty());
+
+        assert!("-Werror".parse::<FlagReplacement>().is_err());
+    }
+
+    #[test]
+    fn pattern_takes_one_or_two_globs() {
+        assert!("-Xclang -fcolor-*".parse::<FlagPattern>().is_ok());
+        assert!("a b c".parse::<FlagPattern>().is_err());
+        assert!("'-Wall".parse::<FlagPattern>().is_err());
+    }
+
+    #[test]
+    fn removes_flags_with_their_separate_values() {
+        let rules = rules(&["-include", "-Wall"], &[], &[]);
+        assert_eq!(
+            rules.apply(&argv(&["cc", "-include", "pch.h", "-Wall", "-c", "a.c"])),
+            argv(&["cc", "-c", "a.c"])
+        );
+    }
+
+    #[test]
+    fn removing_a_value_removes_its_flag() {
+        let rules = rules(&["-fno-pch-timestamp"], &[], &[]);
+        assert_eq!(
+            rules.apply(&argv(&["cc", "-Xclang", "-fno-pch-timestamp", "-Wall"])),
+            argv(&["cc", "-Wall"])
+        );
+    }
+
+    #[test]
+    fn values_of_kept_flags_are_left_alone() {
+        let rules = rules(&[], &["-Wall=-Wextra"], &["-g"]);
+        assert_eq!(
+            rules.apply(&argv(&["cc", "-Wall", "-Xclang", "-Wall", "-o", "-Wall"])),
+            argv(&["cc", "-Wextra", "-Xclang", "-Wall", "-o", "-Wall", "-g"])
+        );
+    }
+
+    #[test]
+    fn two_glob_patterns_match_flag_and_value() {
+        let rules = rules(&["-Xclang -fcolor-*"], &[], &[]);
+        assert_eq!(
+            rules.apply(&argv(&[
+                "cc",
+                "-Xclang",
+                "-fcolor-diagnostics",
+                "-Xclang",
+                "-v"
+            ])),
+            argv(&["cc", "-Xclang", "-v"])
+        );
+    }
+
+    #[test]
+    fn never_touches_the_compiler() {
+        let rules = rules(&["cc"], &["cc=gcc"], &[]);
+        assert_eq!(rules.apply(&argv(&["cc", "-c"])), argv(&["cc", "-c"]));
+    }
+}
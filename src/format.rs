@@ -0,0 +1,69 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{shell, Entry, Result};
+
+/// Form the compile command of joined entries is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFormat {
+    /// Keep whatever form each input uses.
+    Keep,
+    /// Write `arguments` (argv list) only.
+    Arguments,
+    /// Write `command` (a single shell-quoted string) only.
+    Command,
+}
+
+impl FromStr for CommandFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(CommandFormat::Keep),
+            "arguments" => Ok(CommandFormat::Arguments),
+            "command" => Ok(CommandFormat::Command),
+            _ => Err(format!(
+                "unknown command format `{}` (expected `arguments`, `command` or `keep`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CommandFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CommandFormat::Keep => "keep",
+            CommandFormat::Arguments => "arguments",
+            CommandFormat::Command => "command",
+        })
+    }
+}
+
+/// Converts compile commands of all `entries` to `format`.
+///
+/// When an entry has both forms, `arguments` wins as it's unambiguous. Entries with neither are left alone.
+pub fn convert_commands(entries: &mut [Entry], format: CommandFormat) -> Result<()> {
+    if format == CommandFormat::Keep {
+        return Ok(());
+    }
+    for entry in entries {
+        if entry.command.arguments.is_none() && entry.command.command.is_none() {
+            continue;
+        }
+        let argv = entry.argv()?;
+        let command = &mut entry.command;
+        match format {
+            CommandFormat::Arguments => {
+                command.arguments = Some(argv);
+                command.command = None;
+            }
+            CommandFormat::Command => {
+                command.command = Some(shell::join(&argv));
+                command.arguments = None;
+            }
+            CommandFormat::Keep => unreachable!(),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A shell-style wildcard pattern.
+///
+/// Supported syntax:
+/// - `?` matches any single character except `/`
+/// - `*` matches any sequence of characters except `/`
+/// - `**` as a whole path component matches any number of directories, e.g. `a/**/b` matches `a/b` and `a/x/y/b`
+/// - `[abc]`, `[a-z]` match a single character out of a set, `[!abc]` or `[^abc]` one outside of it
+/// - `\` escapes the following character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnySequence,
+    /// `**/`: nothing or any sequence of characters ending with `/`.
+    AnyDirectories,
+    /// Trailing `/**`: `/` followed by anything.
+    AnyRecursive,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// Invalid glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobError {
+    pattern: String,
+    reason: &'static str,
+}
+
+impl GlobError {
+    pub(crate) fn new(pattern: &str, reason: &'static str) -> Self {
+        GlobError {
+            pattern: pattern.to_string(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern `{}`: {}", self.pattern, self.reason)
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+impl Glob {
+    /// Compiles `pattern`.
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let error = |reason| GlobError::new(pattern, reason);
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '?' => tokens.push(Token::AnyChar),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    let next = chars.get(i + 2);
+                    if starts_component && next == Some(&'/') {
+                        tokens.push(Token::AnyDirectories);
+                        i += 2;
+                    } else if starts_component && next.is_none() && i > 0 {
+                        // `a/**` -> replace the already pushed `/`
+                        tokens.pop();
+                        tokens.push(Token::AnyRecursive);
+                        i += 1;
+                    } else if starts_component && next.is_none() {
+                        // just `**` matches everything
+                        tokens.push(Token::AnyDirectories);
+                        tokens.push(Token::AnySequence);
+                        i += 1;
+                    } else {
+                        // `**` which isn't a whole component is just `*`
+                        tokens.push(Token::AnySequence);
+                        i += 1;
+                    }
+                }
+                '*' => tokens.push(Token::AnySequence),
+                '[' => {
+                    let mut j = i + 1;
+                    let negated = matches!(chars.get(j), Some('!') | Some('^'));
+                    if negated {
+                        j += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    let mut first = true;
+                    loop {
+                        let c = match chars.get(j) {
+                            None => return Err(error("unclosed character class")),
+                            // `]` right at the start is a literal
+                            Some(']') if !first => break,
+                            Some('\\') => {
+                                j += 1;
+                                *chars.get(j).ok_or_else(|| error("dangling escape"))?
+                            }
+                            Some(&c) => c,
+                        };
+                        first = false;
+                        if chars.get(j + 1) == Some(&'-')
+                            && chars.get(j + 2).is_some_and(|&c| c != ']')
+                        {
+                            let end = chars[j + 2];
+                            if end < c {
+                                return Err(error("invalid character range"));
+                            }
+                            ranges.push((c, end));
+                            j += 3;
+                        } else {
+                            ranges.push((c, c));
+                            j += 1;
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    i = j;
+                }
+                '\\' => {
+                    i += 1;
+                    let c = *chars.get(i).ok_or_else(|| error("dangling escape"))?;
+                    tokens.push(Token::Literal(c));
+                }
+                c => tokens.push(Token::Literal(c)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    /// The original pattern.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Checks whether the whole `text` matches the pattern.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        matches_at(&self.tokens, &text)
+    }
+}
+
+fn matches_at(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match token {
+        Token::Literal(c) => text.first() == Some(c) && matches_at(rest, &text[1..]),
+        Token::AnyChar => text.first().is_some_and(|&c| c != '/') && matches_at(rest, &text[1..]),
+        Token::Class { negated, ranges } => {
+            text.first().is_some_and(|&c| {
+                c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+            }) && matches_at(rest, &text[1..])
+        }
+        Token::AnySequence => {
+            for i in 0..=text.len() {
+                if matches_at(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Token::AnyDirectories => {
+            if matches_at(rest, text) {
+                return true;
+            }
+            text.iter()
+                .enumerate()
+                .filter(|(_, &c)| c == '/')
+                .any(|(i, _)| matches_at(rest, &text[i + 1..]))
+        }
+        Token::AnyRecursive => text.first() == Some(&'/') && matches_at(rest, &[]),
+    }
+}
+
+impl FromStr for Glob {
+    type Err = GlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Glob::new(s)
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn wildcards_stay_within_a_component() {
+        assert!(is_match("*.json", "compile_commands.json"));
+        assert!(!is_match("*.json", "build/compile_commands.json"));
+        assert!(is_match("a?c", "abc"));
+        assert!(!is_match("a?c", "a/c"));
+        assert!(!is_match("a?c", "ac"));
+        assert!(is_match("-fno-*", "-fno-exceptions"));
+        assert!(!is_match("-fno-*", "-fexceptions"));
+    }
+
+    #[test]
+    fn double_star_matches_any_directories() {
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(!is_match("a/**/b", "a/xb"));
+        assert!(is_match("**/build", "build"));
+        assert!(is_match("**/build", "x/y/build"));
+        assert!(is_match("out/**", "out/Debug/compile_commands.json"));
+        assert!(!is_match("out/**", "out"));
+        assert!(is_match("**", "any/thing"));
+        // not a whole component, so just `*`
+        assert!(is_match("a**b", "axxb"));
+        assert!(!is_match("a**b", "a/b"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(is_match("[abc]", "b"));
+        assert!(!is_match("[abc]", "d"));
+        assert!(is_match("[a-z0-9]x", "7x"));
+        assert!(!is_match("[a-z]", "A"));
+        assert!(is_match("[!a-z]", "A"));
+        assert!(is_match("[^a-z]", "A"));
+        assert!(!is_match("[!a-z]", "q"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match(r"[\]]", "]"));
+        assert!(!is_match("[!x]", "/"));
+    }
+
+    #[test]
+    fn escapes_are_literal() {
+        assert!(is_match(r"\*", "*"));
+        assert!(!is_match(r"\*", "x"));
+        assert!(is_match(r"a\?", "a?"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("[z-a]").is_err());
+        assert!(Glob::new("abc\\").is_err());
+    }
+}
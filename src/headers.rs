@@ -0,0 +1,349 @@
+use std::cmp::Reverse;
+use std::collections::btree_map::Entry as MapEntry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::executable_name;
+use crate::paths::{absolute, input_directory, normalize, relative_to};
+use crate::{Entry, Result};
+
+/// File extensions of headers, other files are never given an entry by [`synthesize_headers`].
+pub const HEADER_EXTENSIONS: &[&str] =
+    &["h", "hh", "hpp", "hxx", "h++", "inl", "ipp", "tcc", "tpp"];
+
+/// Flags adding a directory searched for both `#include "..."` and `#include <...>`.
+const INCLUDE_FLAGS: &[&str] = &["-I", "--include-directory"];
+
+/// Flags adding a directory searched for `#include "..."` only.
+const QUOTE_INCLUDE_FLAGS: &[&str] = &["-iquote"];
+
+/// A compile command of a source file which may lend its flags to headers.
+struct Source {
+    /// Index of the entry within the joined entries.
+    index: usize,
+    /// Working directory of the command.
+    directory: PathBuf,
+    file: PathBuf,
+    argv: Vec<String>,
+    /// Position of the source file within `argv`.
+    file_argument: usize,
+    include_dirs: Vec<PathBuf>,
+    quote_include_dirs: Vec<PathBuf>,
+}
+
+/// How relevant a source is for a header, higher is better: the same name and directory, including the header, the same
+/// name, the same directory, the number of common path components and finally coming first.
+type Rank = (bool, bool, bool, bool, usize, Reverse<usize>);
+
+fn rank(source: &Source, header: &Path, includes: bool) -> Rank {
+    let same_stem = source.file.file_stem() == header.file_stem();
+    let same_directory = source.file.parent() == header.parent();
+    let common = source
+        .file
+        .components()
+        .zip(header.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (
+        same_stem && same_directory,
+        includes,
+        same_stem,
+        same_directory,
+        common,
+        Reverse(source.index),
+    )
+}
+
+/// Returns the longest path both `a` and `b` start with.
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect()
+}
+
+/// Adds entries for headers used by the sources of `entries`, so tools like clangd know how to parse them.
+///
+/// Headers are the files with one of the [`HEADER_EXTENSIONS`] found next to the sources or directly in their include
+/// directories (`-I` and `-iquote`, system ones are skipped) within the joined projects, as well as the ones included
+/// by the sources directly. Include directories outside of the common directory of the sources and build directories of
+/// an input (like `/usr/include`) aren't searched. Every header without an entry of its own gets the flags of the most
+/// relevant source using it: preferably one next to it with the same name (`foo.cpp` for `foo.h`), then one including
+/// it, then one with the same name or in the same directory, and finally the one sharing the longest path with it.
+///
+/// The borrowed command compiles the header instead of the source, loses its output and is told the header's language
+/// with `-x` (like `c++-header` for headers of C++ sources).
+pub fn synthesize_headers(entries: &mut Vec<Entry>) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let mut known = HashSet::new();
+    let mut sources = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let input_directory = absolute(&current_dir, input_directory(&entry.input));
+        let directory = absolute(&input_directory, &entry.command.directory);
+        let file = absolute(&directory, &entry.command.file);
+        known.insert(file.clone());
+        if is_header(&file) {
+            continue;
+        }
+
+        let argv = entry.argv()?;
+        let file_argument = match argv
+            .iter()
+            .skip(1)
+            .position(|argument| absolute(&directory, Path::new(argument)) == file)
+        {
+            Some(position) => position + 1,
+            // without knowing which argument is the source it can't be swapped for a header
+            None => continue,
+        };
+        let include_dirs = parse_include_dirs(&argv, &directory, INCLUDE_FLAGS);
+        let quote_include_dirs = parse_include_dirs(&argv, &directory, QUOTE_INCLUDE_FLAGS);
+        sources.push(Source {
+            index,
+            directory,
+            file,
+            argv,
+            file_argument,
+            include_dirs,
+            quote_include_dirs,
+        });
+    }
+
+    // include directories outside of the joined projects (like `/usr/include`) aren't scanned
+    let mut project_roots: HashMap<&Path, PathBuf> = HashMap::new();
+    for source in &sources {
+        for path in [source.file.parent(), Some(source.directory.as_path())]
+            .into_iter()
+            .flatten()
+        {
+            project_roots
+                .entry(&entries[source.index].input)
+                .and_modify(|root| *root = common_ancestor(root, path))
+                .or_insert_with(|| path.to_path_buf());
+        }
+    }
+    let in_project = |dir: &Path| project_roots.values().any(|root| dir.starts_with(root));
+
+    // the best source for every header so far (sorted, so the synthesized entries come out in a stable order)
+    let mut headers: BTreeMap<PathBuf, (Rank, usize)> = BTreeMap::new();
+    let mut consider = |header: PathBuf, source_index: usize, includes: bool| {
+        let rank = rank(&sources[source_index], &header, includes);
+        match headers.entry(header) {
+            MapEntry::Occupied(mut best) if best.get().0 < rank => {
+                best.insert((rank, source_index));
+            }
+            MapEntry::Occupied(_) => {}
+            MapEntry::Vacant(vacant) => {
+                vacant.insert((rank, source_index));
+            }
+        }
+    };
+    let mut scanned: HashMap<&Path, Vec<PathBuf>> = HashMap::new();
+    for (source_index, source) in sources.iter().enumerate() {
+        for dir in source.include_dirs.iter().chain(&source.quote_include_dirs) {
+            let found = scanned.entry(dir).or_insert_with(|| {
+                let mut found = Vec::new();
+                if in_project(dir) {
+                    find_headers(dir, &mut found);
+                }
+                found
+            });
+            for header in found.iter() {
+                consider(header.clone(), source_index, false);
+            }
+        }
+        for header in included_headers(source) {
+            consider(header, source_index, true);
+        }
+    }
+
+    // sources next to a header are relevant even if they don't use it
+    let mut by_directory: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (source_index, source) in sources.iter().enumerate() {
+        if let Some(parent) = source.file.parent() {
+            by_directory.entry(parent).or_default().push(source_index);
+        }
+    }
+    for (dir, source_indexes) in &by_directory {
+        let found = match scanned.get(dir) {
+            Some(found) => found.clone(),
+            None => {
+                let mut found = Vec::new();
+                find_headers(dir, &mut found);
+                found
+            }
+        };
+        for header in found {
+            for &source_index in source_indexes {
+                consider(header.clone(), source_index, false);
+            }
+        }
+    }
+
+    let synthesized: Vec<Entry> = headers
+        .into_iter()
+        .filter(|(header, _)| !known.contains(header))
+        .map(|(header, (_, source_index))| {
+            let source = &sources[source_index];
+            header_entry(&entries[source.index], source, header)
+        })
+        .collect();
+    entries.extend(synthesized);
+    Ok(())
+}
+
+fn is_header(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| HEADER_EXTENSIONS.contains(&extension))
+}
+
+/// Collects the directories added by `flags` (in `-Idir`, `-I dir` and `--flag=dir` forms), resolved against
+/// `directory`.
+fn parse_include_dirs(argv: &[String], directory: &Path, flags: &[&str]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut arguments = argv.iter().skip(1);
+    while let Some(argument) = arguments.next() {
+        let value = if flags.contains(&argument.as_str()) {
+            arguments.next().map(String::as_str)
+        } else {
+            flags.iter().find_map(|flag| {
+                let value = argument.strip_prefix(flag)?;
+                // long flags need the `=`, `--include-directory-after` is a different flag
+                if flag.starts_with("--") {
+                    value.strip_prefix('=')
+                } else {
+                    Some(value)
+                }
+            })
+        };
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            dirs.push(absolute(directory, Path::new(value)));
+        }
+    }
+    dirs
+}
+
+/// Collects headers directly within `dir`, skipping hidden files.
+fn find_headers(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // include directories which don't exist (yet) are common
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if is_header(&path) && path.is_file() {
+            found.push(normalize(&path));
+        }
+    }
+}
+
+/// Returns headers included by the source, resolved the way the preprocessor would.
+fn included_headers(source: &Source) -> BTreeSet<PathBuf> {
+    let mut headers = BTreeSet::new();
+    let contents = match fs::read(&source.file) {
+        Ok(contents) => contents,
+        // the source may be generated or gone, it just doesn't tell anything about headers then
+        Err(_) => return headers,
+    };
+    let source_dir = source.file.parent().unwrap_or_else(|| Path::new("/"));
+    for line in String::from_utf8_lossy(&contents).lines() {
+        let (name, quoted) = match parse_include(line) {
+            Some(include) => include,
+            None => continue,
+        };
+        let quoted_dirs = [source_dir.to_path_buf()]
+            .into_iter()
+            .chain(source.quote_include_dirs.iter().cloned());
+        let resolved = quoted_dirs
+            .filter(|_| quoted)
+            .chain(source.include_dirs.iter().cloned())
+            .map(|dir| normalize(&dir.join(name)))
+            .find(|path| path.is_file());
+        if let Some(header) = resolved.filter(|header| is_header(header)) {
+            headers.insert(header);
+        }
+    }
+    headers
+}
+
+/// Parses `#include "name"`, `#include <name>` (or `#import`) into the name and whether it's quoted.
+fn parse_include(line: &str) -> Option<(&str, bool)> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = directive
+        .strip_prefix("include")
+        .or_else(|| directive.strip_prefix("import"))?
+        .trim_start();
+    if let Some(rest) = rest.strip_prefix('"') {
+        rest.split_once('"').map(|(name, _)| (name, true))
+    } else {
+        let rest = rest.strip_prefix('<')?;
+        rest.split_once('>').map(|(name, _)| (name, false))
+    }
+}
+
+/// Language of headers used by a source, as passed to `-x`.
+fn header_language(source: &Path) -> Option<&'static str> {
+    match source.extension()?.to_str()? {
+        "c" => Some("c-header"),
+        "cc" | "cp" | "cpp" | "cxx" | "c++" | "C" | "CPP" => Some("c++-header"),
+        "m" => Some("objective-c-header"),
+        "mm" | "M" => Some("objective-c++-header"),
+        _ => None,
+    }
+}
+
+/// Builds the entry of `header` out of the `entry` of `source`.
+fn header_entry(entry: &Entry, source: &Source, header: PathBuf) -> Entry {
+    // the header is written the same way as the source, absolute or relative to the directory
+    let relative_header = relative_to(&header, &source.directory);
+    let header_argument = if Path::new(&source.argv[source.file_argument]).is_absolute() {
+        header.to_string_lossy().into_owned()
+    } else {
+        relative_header.to_string_lossy().into_owned()
+    };
+    let compiler = executable_name(&source.argv[0]);
+    // an explicit language applies to the header too, MSVC style drivers don't know `-x`
+    let language = header_language(&source.file).filter(|_| {
+        !matches!(compiler, "cl" | "clang-cl")
+            && !source
+                .argv
+                .iter()
+                .any(|argument| argument.starts_with("-x"))
+    });
+
+    let mut argv = Vec::with_capacity(source.argv.len() + 2);
+    let mut arguments = source.argv.iter().enumerate();
+    while let Some((position, argument)) = arguments.next() {
+        if position == source.file_argument {
+            if let Some(language) = language {
+                argv.extend(["-x".to_string(), language.to_string()]);
+            }
+            argv.push(header_argument.clone());
+        } else if argument == "-o" || argument == "--output" {
+            // the object file belongs to the source
+            arguments.next();
+        } else if !(argument.starts_with("--output=") || argument.starts_with("/Fo")) {
+            argv.push(argument.clone());
+        }
+    }
+
+    let mut command = entry.command.clone();
+    command.file = if command.file.is_absolute() {
+        header
+    } else {
+        relative_header
+    };
+    command.output = None;
+    command.set_argv(argv);
+    Entry {
+        input: entry.input.clone(),
+        command,
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::glob::{Glob, GlobError};
+
+/// A path pattern following `.gitignore` rules, used for lines of ignore files as well as include and exclude patterns.
+///
+/// Patterns containing a `/` (other than a trailing one) match paths relative to a base directory, others match just
+/// the name at any depth. A trailing `/` restricts the pattern to directories, a leading `!` negates it.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    source: String,
+    glob: Glob,
+    /// `!pattern` re-includes what earlier rules excluded.
+    negated: bool,
+    /// `pattern/` only matches directories.
+    dir_only: bool,
+    /// Patterns containing a `/` other than a trailing one match paths relative to the base, others just names.
+    anchored: bool,
+}
+
+impl PathPattern {
+    /// Parses a single pattern, returns `None` for blank lines and comments.
+    pub(crate) fn parse(line: &str) -> Option<Result<Self, GlobError>> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        // trailing spaces are ignored unless escaped
+        let trimmed = line.trim_end_matches(' ');
+        let line = if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+            &line[..trimmed.len() + 1]
+        } else {
+            trimmed
+        };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let source = line.to_string();
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(Glob::new(line).map(|glob| PathPattern {
+            source,
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        }))
+    }
+
+    /// Checks whether `relative` (a `/` separated path relative to the base directory) matches the pattern.
+    pub fn is_match(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.is_match(relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.glob.is_match(name)
+        }
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = GlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PathPattern::parse(s).unwrap_or_else(|| Err(GlobError::new(s, "empty pattern or comment")))
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Rules of a single ignore file applying to everything under `base`.
+#[derive(Debug, Clone)]
+pub(crate) struct IgnoreRules {
+    base: PathBuf,
+    rules: Vec<PathPattern>,
+}
+
+impl IgnoreRules {
+    /// Parses the contents of an ignore file located in `base`, invalid patterns are skipped like git does.
+    pub(crate) fn parse(base: PathBuf, contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .filter_map(PathPattern::parse)
+            .filter_map(Result::ok)
+            .collect();
+        IgnoreRules { base, rules }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns `Some(true)` if the last rule matching `path` ignores it, `Some(false)` if it re-includes it.
+    pub(crate) fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = relative_path(&self.base, path)?;
+        matched_patterns(&self.rules, &relative, is_dir)
+    }
+}
+
+/// Returns `Some(true)` if the last pattern matching `relative` is a positive one, `Some(false)` if it's negated.
+pub(crate) fn matched_patterns(
+    patterns: &[PathPattern],
+    relative: &str,
+    is_dir: bool,
+) -> Option<bool> {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.is_match(relative, is_dir))
+        .map(|pattern| !pattern.negated)
+}
+
+/// Converts `path` under `base` into a `/` separated relative path patterns are matched against.
+pub(crate) fn relative_path(base: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base).ok()?;
+    Some(
+        relative
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/"),
+    )
+}
+
+/// Ignore rules of a directory and all its parents, the deepest ones take precedence.
+#[derive(Debug)]
+pub(crate) struct IgnoreStack {
+    rules: IgnoreRules,
+    parent: Option<Arc<IgnoreStack>>,
+}
+
+impl IgnoreStack {
+    pub(crate) fn push(
+        parent: Option<Arc<IgnoreStack>>,
+        rules: IgnoreRules,
+    ) -> Option<Arc<IgnoreStack>> {
+        if rules.is_empty() {
+            return parent;
+        }
+        Some(Arc::new(IgnoreStack { rules, parent }))
+    }
+
+    pub(crate) fn is_ignored(stack: &Option<Arc<IgnoreStack>>, path: &Path, is_dir: bool) -> bool {
+        let mut current = stack.as_deref();
+        while let Some(stack) = current {
+            if let Some(ignored) = stack.rules.matched(path, is_dir) {
+                return ignored;
+            }
+            current = stack.parent.as_deref();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(line: &str) -> PathPattern {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn patterns_without_slash_match_names_at_any_depth() {
+        assert!(pattern("build").is_match("build", true));
+        assert!(pattern("build").is_match("a/b/build", true));
+        assert!(pattern("*.json").is_match("a/compile_commands.json", false));
+        assert!(!pattern("build").is_match("build/x", false));
+    }
+
+    #[test]
+    fn patterns_with_slash_are_anchored() {
+        assert!(pattern("out/Debug").is_match("out/Debug", true));
+        assert!(!pattern("out/Debug").is_match("x/out/Debug", true));
+        assert!(pattern("/build").is_match("build", true));
+        assert!(!pattern("/build").is_match("a/build", true));
+        assert!(pattern("**/Debug").is_match("a/b/Debug", true));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        assert!(pattern("build/").is_match("a/build", true));
+        assert!(!pattern("build/").is_match("a/build", false));
+    }
+
+    #[test]
+    fn parses_comments_blank_lines_and_spaces() {
+        assert!(PathPattern::parse("# comment").is_none());
+        assert!(PathPattern::parse("   ").is_none());
+        assert!(pattern("build   ").is_match("build", true));
+        assert!(pattern("build\\ ").is_match("build ", true));
+        assert!(pattern("\\#file").is_match("#file", false));
+    }
+
+    #[test]
+    fn negation_re_includes() {
+        let patterns = [pattern("*.json"), pattern("!keep.json")];
+        assert_eq!(matched_patterns(&patterns, "a/b.json", false), Some(true));
+        assert_eq!(
+            matched_patterns(&patterns, "a/keep.json", false),
+            Some(false)
+        );
+        assert_eq!(matched_patterns(&patterns, "a/b.txt", false), None);
+    }
+
+    #[test]
+    fn deeper_ignore_files_take_precedence() {
+        let root = IgnoreStack::push(None, IgnoreRules::parse(PathBuf::from("/p"), "build/\n"));
+        let nested = IgnoreStack::push(
+            root.clone(),
+            IgnoreRules::parse(PathBuf::from("/p/sub"), "!build/\n"),
+        );
+        assert!(IgnoreStack::is_ignored(
+            &root,
+            Path::new("/p/sub/build"),
+            true
+        ));
+        assert!(!IgnoreStack::is_ignored(
+            &nested,
+            Path::new("/p/sub/build"),
+            true
+        ));
+        assert!(IgnoreStack::is_ignored(
+            &nested,
+            Path::new("/p/build"),
+            true
+        ));
+        // rules only apply below their own directory
+        assert!(!IgnoreStack::is_ignored(&root, Path::new("/q/build"), true));
+    }
+}
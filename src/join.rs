@@ -0,0 +1,233 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::de::{self, DeserializeOwned, Deserializer, SeqAccess, Visitor};
+
+use crate::{CompileCommand, Entry, Error, Result};
+
+/// Reads and parses a single compilation database file.
+pub fn load<P>(path: P) -> Result<Vec<CompileCommand>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let input = io::BufReader::new(
+        fs::File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?,
+    );
+    let commands =
+        serde_json::from_reader(input).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(commands)
+}
+
+/// Reads a single compilation database file calling `f` with one entry at a time.
+///
+/// Unlike [`load_entries`](crate::load_entries) only a single entry is held in memory, so databases of any size can be
+/// processed. Reading stops at the first error returned by `f`, or at the first malformed entry, after all entries
+/// before it have been passed to `f` already.
+pub fn for_each_entry<P, F>(path: P, mut f: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(Entry) -> Result<()>,
+{
+    let path = path.as_ref();
+    let input: Arc<Path> = Arc::from(path);
+    for_each_element(path, |command| {
+        f(Entry {
+            input: input.clone(),
+            command,
+        })
+    })
+    .map_err(|err| match err {
+        ElementError::Read(reason) => format!("{}: {}", path.display(), reason).into(),
+        ElementError::Callback(err) => err,
+    })
+}
+
+/// Failure of [`for_each_element`].
+pub(crate) enum ElementError {
+    /// The file can't be read or isn't a valid JSON list.
+    Read(String),
+    /// The callback failed.
+    Callback(Error),
+}
+
+/// Reads the JSON list in the file at `path` calling `f` with one parsed element at a time.
+pub(crate) fn for_each_element<T, F>(path: &Path, f: F) -> std::result::Result<(), ElementError>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<()>,
+{
+    let input = io::BufReader::new(
+        fs::File::open(path).map_err(|err| ElementError::Read(err.to_string()))?,
+    );
+    let mut deserializer = serde_json::Deserializer::from_reader(input);
+    let mut visitor = ElementVisitor {
+        f,
+        error: None,
+        element: PhantomData,
+    };
+    let result = deserializer
+        .deserialize_seq(&mut visitor)
+        .and_then(|()| deserializer.end());
+    match (visitor.error, result) {
+        // errors of `f` are passed through serde as plain messages, return the original instead
+        (Some(err), _) => Err(ElementError::Callback(err)),
+        (None, Err(err)) => Err(ElementError::Read(err.to_string())),
+        (None, Ok(())) => Ok(()),
+    }
+}
+
+struct ElementVisitor<T, F> {
+    f: F,
+    error: Option<Error>,
+    element: PhantomData<T>,
+}
+
+impl<'de, T, F> Visitor<'de> for &mut ElementVisitor<T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of compile commands")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(element) = seq.next_element()? {
+            if let Err(err) = (self.f)(element) {
+                let message = err.to_string();
+                self.error = Some(err);
+                return Err(de::Error::custom(message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes compile commands one at a time as a single compilation database.
+///
+/// Produces the same output as [`join`] while holding just a single entry in memory. Call [`JoinWriter::finish`]
+/// once all commands are written, otherwise the database is left incomplete.
+pub struct JoinWriter<W: Write> {
+    output: io::BufWriter<W>,
+    /// The current entry serialized on its own, before getting indented into the list.
+    buffer: Vec<u8>,
+    empty: bool,
+}
+
+impl<W: Write> JoinWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JoinWriter {
+            output: io::BufWriter::new(writer),
+            buffer: Vec::new(),
+            empty: true,
+        }
+    }
+
+    /// Appends `command` to the database.
+    pub fn write(&mut self, command: &CompileCommand) -> Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer_pretty(&mut self.buffer, command)?;
+        self.output
+            .write_all(if self.empty { b"[\n" } else { b",\n" })?;
+        self.empty = false;
+        // newlines within strings are escaped, so every newline starts a new line of the pretty printed entry
+        for (i, line) in self.buffer.split(|&byte| byte == b'\n').enumerate() {
+            if i > 0 {
+                self.output.write_all(b"\n")?;
+            }
+            self.output.write_all(b"  ")?;
+            self.output.write_all(line)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the list of entries and flushes the output.
+    pub fn finish(mut self) -> Result<()> {
+        self.output
+            .write_all(if self.empty { b"[]\n" } else { b"\n]\n" })?;
+
+        // flush before dropping the writer
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Writes all `commands` to `writer` as a single compilation database.
+pub fn join<I, W>(commands: I, writer: W) -> Result<()>
+where
+    I: IntoIterator<Item = CompileCommand>,
+    W: Write,
+{
+    // write all entries as a single json list
+    let mut output = JoinWriter::new(writer);
+    for command in commands {
+        output.write(&command)?;
+    }
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(file: &str, arguments: Option<&[&str]>) -> CompileCommand {
+        CompileCommand {
+            directory: "/src".into(),
+            file: file.into(),
+            command: arguments
+                .is_none()
+                .then(|| format!("cc -DMSG=\"a\\nb\" -c {}", file)),
+            arguments: arguments
+                .map(|arguments| arguments.iter().map(|arg| arg.to_string()).collect()),
+            output: Some(format!("{}.o", file).into()),
+        }
+    }
+
+    /// What the database written all at once looks like.
+    fn pretty(commands: &[CompileCommand]) -> Vec<u8> {
+        let mut expected = serde_json::to_vec_pretty(commands).unwrap();
+        expected.push(b'\n');
+        expected
+    }
+
+    #[test]
+    fn streamed_output_matches_whole_database() {
+        let cases = [
+            Vec::new(),
+            vec![command("a.c", None)],
+            vec![
+                command("a.c", None),
+                command("b.c", Some(&["cc", "-c", "b.c", "-o", "b.c.o"])),
+                command("c.c", Some(&[])),
+            ],
+        ];
+        for commands in &cases {
+            let mut streamed = Vec::new();
+            let mut writer = JoinWriter::new(&mut streamed);
+            for command in commands {
+                writer.write(command).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(
+                String::from_utf8(streamed).unwrap(),
+                String::from_utf8(pretty(commands)).unwrap()
+            );
+
+            let mut joined = Vec::new();
+            join(commands.iter().cloned(), &mut joined).unwrap();
+            assert_eq!(joined, pretty(commands));
+        }
+    }
+}
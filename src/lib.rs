@@ -0,0 +1,68 @@
+//! Discover, load and join multiple [`compile_commands.json`](https://clang.llvm.org/docs/JSONCompilationDatabase.html)
+//! files into one.
+//!
+//! The binary is a thin wrapper around this library, so build tooling can run the joiner in-process:
+//!
+//! ```no_run
+//! # async fn example() -> join_compile_commands_json::Result<()> {
+//! let mut paths = join_compile_commands_json::discover(vec!["build"]);
+//! let mut commands = Vec::new();
+//! while let Some(path) = paths.recv().await {
+//!     commands.extend(join_compile_commands_json::load(path?.path)?);
+//! }
+//! join_compile_commands_json::join(commands, std::io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod cache;
+mod check;
+mod compile_command;
+mod compiler;
+mod dedup;
+mod discover;
+mod entry;
+mod flags;
+mod format;
+mod glob;
+mod headers;
+mod ignore;
+mod join;
+mod marker;
+mod order;
+mod output;
+mod paths;
+mod rewrite;
+pub mod shell;
+#[cfg(target_os = "linux")]
+mod watch;
+
+pub use cache::{cache_path, content_hash, InputCache};
+pub use check::{check_inputs, Problem, ProblemKind};
+pub use compile_command::CompileCommand;
+pub use compiler::{substitute_compiler, target_from_compiler, CompilerRules, KNOWN_LAUNCHERS};
+pub use dedup::{dedup_entries, DroppedDuplicate, DuplicatePolicy};
+pub use discover::{
+    default_jobs, discover, discover_with, DiscoverError, DiscoverOptions, Discovered, Found,
+    COMPILE_COMMANDS_JSON_FILE_NAME,
+};
+pub use entry::{load_entries, Entry};
+pub use flags::{filter_flags, FlagPattern, FlagReplacement, FlagRules};
+pub use format::{convert_commands, CommandFormat};
+pub use glob::{Glob, GlobError};
+pub use headers::{synthesize_headers, HEADER_EXTENSIONS};
+pub use ignore::PathPattern;
+pub use join::{for_each_entry, join, load, JoinWriter};
+pub use marker::{joined_marker_path, write_joined_marker};
+pub use order::{sort_entries, SortKey, DEFAULT_SORT_KEYS};
+pub use output::write_atomically;
+pub use rewrite::{map_prefixes, relativize_paths, rewrite_paths, PathStyle, PrefixMapping};
+pub use shell::ShellError;
+#[cfg(target_os = "linux")]
+pub use watch::watch;
+
+/// Error type used throughout the library.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Result type used throughout the library.
+pub type Result<T> = std::result::Result<T, Error>;
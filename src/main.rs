@@ -1,119 +1,383 @@
-use std::fs;
 use std::io;
-use std::io::BufRead;
-use std::io::Read;
-use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
 
-use tokio::{sync::mpsc, task::JoinHandle};
+#[cfg(target_os = "linux")]
+use join_compile_commands_json::watch;
+use join_compile_commands_json::{
+    check_inputs, content_hash, convert_commands, dedup_entries, default_jobs, discover_with,
+    filter_flags, for_each_entry, join, load_entries, map_prefixes, relativize_paths,
+    rewrite_paths, sort_entries, substitute_compiler, synthesize_headers, write_atomically,
+    write_joined_marker, DiscoverOptions, DuplicatePolicy, Entry, Error, InputCache, JoinWriter,
+    PathStyle, Result,
+};
+use tokio::sync::Semaphore;
 
-const COMPILE_COMMANDS_JSON_FILE_NAME: &str = "compile_commands.json";
+mod cli;
 
-fn spawn_compile_commands_search<P>(
-    path: P,
-    results_channel: mpsc::Sender<PathBuf>,
-) -> JoinHandle<()>
-where
-    P: AsRef<Path> + Send + 'static,
-{
-    tokio::spawn(async move {
-        find_compile_commands_files(path, results_channel)
-            .await
-            .unwrap();
-    })
+use cli::{Command, Options, Output};
+
+/// How long `--watch` waits for further changes before joining again.
+#[cfg(target_os = "linux")]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run().await {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
 }
 
-async fn find_compile_commands_files<P>(
-    path: P,
-    results_channel: mpsc::Sender<PathBuf>,
-) -> Result<(), Box<dyn std::error::Error>>
-where
-    P: AsRef<Path>,
-{
-    let mut dir_contents = tokio::fs::read_dir(path).await?;
-    while let Some(entry) = dir_contents.next_entry().await? {
-        if entry.file_type().await?.is_dir() {
-            // spawn a new search in subdir
-            spawn_compile_commands_search(entry.path(), results_channel.clone());
-        } else if entry.file_name() == COMPILE_COMMANDS_JSON_FILE_NAME {
-            // compile_commands.json file found -> send it over the channel
-            results_channel.send(entry.path()).await?;
+async fn run() -> Result<()> {
+    // skip the first arg (name of the binary)
+    let command = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("error: {}\n\nFor more information, try `--help`.", err);
+            std::process::exit(2);
         }
+    };
+    let (mut options, check_only) = match command {
+        Command::Join(options) => (*options, false),
+        Command::Check(options) => (*options, true),
+        Command::Help => {
+            print!("{}", cli::usage());
+            return Ok(());
+        }
+        Command::Version => {
+            println!("{}", cli::version());
+            return Ok(());
+        }
+    };
+    if options.inputs.is_empty() {
+        // default to current directory
+        options.inputs.push(std::env::current_dir()?);
+    }
+
+    if check_only {
+        check(options).await
+    } else if options.watch {
+        watch_and_join(options).await
+    } else {
+        join_inputs(&options).await
+    }
+}
+
+/// Applies the transformations of `options` following the path rewriting (and header synthesis) to `entries`.
+fn transform_entries(entries: &mut [Entry], options: &Options) -> Result<()> {
+    map_prefixes(entries, &options.prefix_mappings)?;
+    if options.path_style == PathStyle::Relative {
+        // relative to where the output goes, stdout is most likely redirected to the current directory
+        let base = match &options.output {
+            Output::File(path) => path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+            Output::Stdout => PathBuf::new(),
+        };
+        relativize_paths(entries, &base)?;
     }
+    substitute_compiler(entries, &options.compiler_rules)?;
+    filter_flags(entries, &options.flag_rules)?;
+    convert_commands(entries, options.format)?;
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // skip the first arg (name of the binary)
-    let args: Vec<String> = std::env::args().skip(1).collect();
+/// Discovery options for the searched inputs of `options`.
+fn discover_options(options: &Options) -> DiscoverOptions {
+    DiscoverOptions {
+        include_joined: options.include_joined,
+        jobs: options.jobs,
+        ignore_files: options.ignore_files.clone(),
+        exclude: options.exclude.clone(),
+        include: options.include.clone(),
+        follow_symlinks: options.follow_symlinks,
+        max_depth: options.max_depth,
+        min_depth: options.min_depth,
+        names: options.names.clone(),
+        ..DiscoverOptions::default()
+    }
+}
 
-    // create channel to pass the compile_command.json paths from search tasks back to joining
-    let (tx, mut rx) = mpsc::channel(32);
-    if args.is_empty() {
-        // default to current directory
-        spawn_compile_commands_search(std::env::current_dir()?, tx.clone());
-    } else {
-        // search in all directories provided as arguments
-        for path in args.into_iter() {
-            spawn_compile_commands_search(path, tx.clone());
+/// Searches the inputs of `options` and calls `found` with every database, returns the number of failures.
+///
+/// Failures of the search as well as of `found` are reported with [`report_failure`].
+async fn search<F>(options: &Options, discover_options: DiscoverOptions, mut found: F) -> usize
+where
+    F: FnMut(PathBuf) -> Result<()>,
+{
+    // search in all directories provided as arguments, files are passed through as they are
+    let mut paths = discover_with(options.inputs.clone(), discover_options);
+
+    let mut failures = 0;
+    while let Some(discovered) = paths.recv().await {
+        let result = discovered.map_err(Error::from).and_then(|path| {
+            if let Some(resolved) = &path.resolved {
+                eprintln!(
+                    "note: {} is reached through a symlink, it's {}",
+                    path.path.display(),
+                    resolved.display()
+                );
+            }
+            found(path.path)
+        });
+        if let Err(err) = result {
+            report_failure(options, &err);
+            failures += 1;
         }
     }
+    failures
+}
 
-    // all spawn calls have a clone so let's drop the last instance so the rx.recv finishes when all tasks drop their tx
-    drop(tx);
+/// Reports a failure to search or read an input, as a warning with `--keep-going` and as an error otherwise.
+fn report_failure(options: &Options, err: &Error) {
+    // report every failure, strict mode bails out only once all of them are known
+    let severity = if options.keep_going {
+        "warning"
+    } else {
+        "error"
+    };
+    eprintln!("{}: {}", severity, err);
+}
 
-    // open output file for writing
-    let mut output = io::BufWriter::new(
-        fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(COMPILE_COMMANDS_JSON_FILE_NAME)?,
-    );
+/// Fails if any input couldn't be searched or read, unless `--keep-going` allows joining the rest.
+fn abort_on_failures(options: &Options, failures: usize) -> Result<()> {
+    if failures > 0 && !options.keep_going {
+        return Err(format!(
+            "{} input(s) could not be searched or read, no output written (use `--keep-going` to join the rest)",
+            failures
+        )
+        .into());
+    }
+    Ok(())
+}
 
-    // start json list
-    output.write_all(b"[")?;
-    let mut has_contents = false;
-    while let Some(path) = rx.recv().await {
-        let mut input = io::BufReader::new(fs::File::open(path)?);
-        let mut buffer = Vec::new();
+async fn check(options: Options) -> Result<()> {
+    let mut inputs = Vec::new();
+    let failures = search(&options, discover_options(&options), |path| {
+        inputs.push(path);
+        Ok(())
+    })
+    .await;
 
-        // advance until the list start
-        input.read_until(b'[', &mut buffer)?;
-        // discard what we have read so far
-        buffer.clear();
+    // discovery order is arbitrary so make the report stable
+    inputs.sort();
+    let problems = check_inputs(&inputs);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if failures > 0 || !problems.is_empty() {
+        let mut message = format!(
+            "found {} problem(s) in {} input(s)",
+            problems.len(),
+            inputs.len()
+        );
+        if failures > 0 {
+            message.push_str(&format!(", {} path(s) could not be searched", failures));
+        }
+        return Err(message.into());
+    }
+    eprintln!("checked {} input(s), no problems found", inputs.len());
+    Ok(())
+}
 
-        // read the rest of the file into the buffer
-        input.read_to_end(&mut buffer)?;
+/// Discovery options for joining, which never finds the output itself.
+fn join_discover_options(options: &Options) -> DiscoverOptions {
+    let mut discover_options = discover_options(options);
+    if let Output::File(path) = &options.output {
+        // never read our own (previous or partially written) output
+        discover_options.exclude_paths.push(path.clone());
+    }
+    discover_options
+}
 
-        // drop from the end of the buffer until we find list end
-        while !buffer.is_empty() && buffer.last() != Some(&b']') {
-            buffer.pop();
+/// Joins the inputs once and then again whenever any of the databases changes, until interrupted.
+#[cfg(target_os = "linux")]
+async fn watch_and_join(options: Options) -> Result<()> {
+    // start watching first, so nothing changing during the first run gets lost
+    let mut changes = watch(
+        options.inputs.clone(),
+        join_discover_options(&options),
+        WATCH_DEBOUNCE,
+    )
+    .await?;
+    eprintln!("note: watching for changes, press Ctrl-C to stop");
+    loop {
+        // failures are reported but don't stop watching, the next change may fix them
+        if let Err(err) = join_inputs(&options).await {
+            eprintln!("error: {}", err);
         }
+        let changed = match changes.recv().await {
+            Some(changed) => changed,
+            None => return Ok(()),
+        };
+        match changed.as_slice() {
+            [path] => eprintln!("note: {} changed, joining again", path.display()),
+            paths => eprintln!("note: {} paths changed, joining again", paths.len()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn watch_and_join(_options: Options) -> Result<()> {
+    Err("`--watch` is only supported on Linux".into())
+}
 
-        // drop the list end character
-        if buffer.last() == Some(&b']') {
-            buffer.pop();
+/// Hash of everything besides the inputs the joined database depends on.
+///
+/// Which inputs have been found is compared by the cache itself, so discovery options as well as options not changing
+/// the output (like `--jobs`) are left out.
+fn settings_hash(options: &Options) -> Result<u64> {
+    let settings = format!(
+        "{:?}",
+        (
+            env!("CARGO_PKG_VERSION"),
+            // relative inputs and outputs depend on where the tool runs
+            std::env::current_dir()?,
+            &options.output,
+            &options.sort_keys,
+            &options.duplicates,
+            &options.path_style,
+            &options.prefix_mappings,
+            options.headers,
+            &options.format,
+            &options.flag_rules,
+            &options.compiler_rules,
+        )
+    );
+    Ok(content_hash(settings.as_bytes()))
+}
+
+async fn join_inputs(options: &Options) -> Result<()> {
+    if options.stream {
+        return stream_inputs(options).await;
+    }
+    let cache = match &options.output {
+        Output::File(path) if options.cache => {
+            Some(Arc::new(InputCache::load(path, settings_hash(options)?)))
         }
+        _ => None,
+    };
+
+    // parse found files while the search goes on, a few at a time
+    let jobs = match options.jobs {
+        0 => default_jobs(),
+        jobs => jobs,
+    };
+    let permits = Arc::new(Semaphore::new(jobs));
+    let mut loads = Vec::new();
+    let mut failures = search(options, join_discover_options(options), |path| {
+        let permits = permits.clone();
+        let cache = cache.clone();
+        loads.push(tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            tokio::task::spawn_blocking(move || {
+                let loaded = match &cache {
+                    Some(cache) => cache.load_entries(&path)?,
+                    None => load_entries(&path)?,
+                };
+                Ok((path, loaded))
+            })
+            .await?
+        }));
+        Ok(())
+    })
+    .await;
 
-        // write the buffer to the output file
-        if !buffer.is_empty() {
-            // write delimiter if there's already any contents written to the file
-            if has_contents {
-                output.write_all(b",")?;
-            } else {
-                has_contents = true;
+    // gather entries in the order the inputs have been found, no matter which got parsed first
+    let mut inputs = Vec::new();
+    let mut entries = Vec::new();
+    for load in loads {
+        match load.await.map_err(Error::from).and_then(|loaded| loaded) {
+            Ok((path, loaded)) => {
+                entries.extend(loaded);
+                inputs.push(path);
             }
+            Err(err) => {
+                report_failure(options, &err);
+                failures += 1;
+            }
+        }
+    }
+    abort_on_failures(options, failures)?;
+    if let (Some(cache), Output::File(path)) = (&cache, &options.output) {
+        // synthesized headers depend on files the cache doesn't know about, the newest duplicates on modification times
+        // of inputs the cache considers unchanged as long as their contents are the same
+        let depends_on_more = options.headers || options.duplicates == DuplicatePolicy::KeepNewest;
+        if !depends_on_more && cache.is_up_to_date(path) {
+            eprintln!("note: nothing changed, {} is up to date", path.display());
+            // inputs rewritten with the same contents don't have to be read next time
+            return cache.save(path);
+        }
+    }
+
+    // relative paths are only valid next to the input they come from
+    rewrite_paths(&mut entries, options.path_style)?;
+    if options.headers {
+        // headers are looked up on disk, before paths get mapped to anything else
+        synthesize_headers(&mut entries)?;
+    }
+
+    // discovery order is arbitrary so make the output stable
+    sort_entries(&mut entries, &options.sort_keys);
+    inputs.sort();
+    // duplicates are found by the absolute paths, before they get mapped or made relative to the output
+    for duplicate in dedup_entries(&mut entries, options.duplicates, &options.inputs)? {
+        eprintln!("note: {}", duplicate);
+    }
+    transform_entries(&mut entries, options)?;
+    let commands = entries.into_iter().map(|entry| entry.command);
 
-            output.write_all(&buffer)?;
+    match &options.output {
+        Output::Stdout => join(commands, io::stdout().lock())?,
+        Output::File(path) => {
+            write_atomically(path, |output| join(commands, output))?;
+            write_joined_marker(path, &inputs)?;
+            if let Some(cache) = &cache {
+                cache.save(path)?;
+            }
         }
     }
 
-    // end json list
-    output.write_all(b"]")?;
+    Ok(())
+}
+
+/// Joins the inputs one entry at a time, without ever holding all of them in memory.
+async fn stream_inputs(options: &Options) -> Result<()> {
+    // every input is read twice, first just to make sure broken ones are left out entirely like when not streaming
+    let mut inputs = Vec::new();
+    let failures = search(options, join_discover_options(options), |path| {
+        for_each_entry(&path, |_| Ok(()))?;
+        inputs.push(path);
+        Ok(())
+    })
+    .await;
+    abort_on_failures(options, failures)?;
 
-    // flush before dropping the writer
-    output.flush()?;
+    // discovery order is arbitrary so make the output stable
+    inputs.sort();
+    let write = |output: &mut dyn io::Write| -> Result<()> {
+        let mut writer = JoinWriter::new(output);
+        for input in &inputs {
+            for_each_entry(input, |entry| {
+                let mut entries = [entry];
+                rewrite_paths(&mut entries, options.path_style)?;
+                transform_entries(&mut entries, options)?;
+                let [entry] = entries;
+                writer.write(&entry.command)
+            })?;
+        }
+        writer.finish()
+    };
+
+    match &options.output {
+        Output::Stdout => write(&mut io::stdout().lock())?,
+        Output::File(path) => {
+            // a failure leaves the previous output in place
+            write_atomically(path, |output| write(output))?;
+            write_joined_marker(path, &inputs)?;
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,50 @@
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{write_atomically, Result};
+
+/// Extension appended to the name of a joined database to get the name of its marker file.
+const JOINED_MARKER_EXTENSION: &str = "joined";
+
+#[derive(Serialize)]
+struct JoinedMarker<'a> {
+    generator: String,
+    inputs: &'a [PathBuf],
+}
+
+/// Returns the path of the marker file which flags `database` as produced by this tool.
+pub fn joined_marker_path<P>(database: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let database = database.as_ref();
+    let mut name = database.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(JOINED_MARKER_EXTENSION);
+    database.with_file_name(name)
+}
+
+/// Writes the marker file flagging `database` as produced by this tool from `inputs`.
+///
+/// Nothing is written for databases which aren't regular files, like `/dev/stdout`.
+pub fn write_joined_marker<P>(database: P, inputs: &[PathBuf]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let database = database.as_ref();
+    if !database.is_file() {
+        return Ok(());
+    }
+    let marker = JoinedMarker {
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        inputs,
+    };
+    write_atomically(joined_marker_path(database), |file| {
+        serde_json::to_writer_pretty(&mut *file, &marker)?;
+        file.write_all(b"\n")?;
+        Ok(())
+    })
+}
@@ -0,0 +1,76 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Entry;
+
+/// Key entries of the joined database can be ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Path of the database file the entry has been loaded from, entries keep their original order within it.
+    Input,
+    /// The `file` field.
+    File,
+    /// The `directory` field.
+    Directory,
+    /// The `output` field, entries without one go first.
+    Output,
+}
+
+/// Ordering used unless requested otherwise: by input path, then by entry file.
+pub const DEFAULT_SORT_KEYS: &[SortKey] = &[SortKey::Input, SortKey::File];
+
+impl SortKey {
+    fn compare(self, a: &Entry, b: &Entry) -> Ordering {
+        match self {
+            SortKey::Input => a.input.cmp(&b.input),
+            SortKey::File => a.command.file.cmp(&b.command.file),
+            SortKey::Directory => a.command.directory.cmp(&b.command.directory),
+            SortKey::Output => a.command.output.cmp(&b.command.output),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input" => Ok(SortKey::Input),
+            "file" => Ok(SortKey::File),
+            "directory" => Ok(SortKey::Directory),
+            "output" => Ok(SortKey::Output),
+            _ => Err(format!(
+                "unknown sort key `{}` (expected `input`, `file`, `directory` or `output`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortKey::Input => "input",
+            SortKey::File => "file",
+            SortKey::Directory => "directory",
+            SortKey::Output => "output",
+        })
+    }
+}
+
+/// Sorts `entries` by `keys`, earlier keys take precedence.
+///
+/// The sort is stable so entries equal in all keys keep their relative order. With no keys the order is left as it
+/// is.
+pub fn sort_entries(entries: &mut [Entry], keys: &[SortKey]) {
+    if keys.is_empty() {
+        return;
+    }
+    entries.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
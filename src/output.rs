@@ -0,0 +1,67 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+/// Atomically replaces the file at `path` with contents produced by `write`.
+///
+/// The contents are written to a temporary file in the same directory which then gets renamed over `path`, so readers
+/// never see a partially written file. If `write` or any of the file operations fail the temporary file is removed and
+/// the original file is left intact. The new file keeps the permissions of the original one. A symlink at `path` gets
+/// replaced by the new file, the file it points to (which might well be one of the inputs) is never touched. Existing
+/// paths which aren't regular files (like `/dev/stdout` or a named pipe) can't be replaced and are written to
+/// directly.
+pub fn write_atomically<P, F>(path: P, write: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut fs::File) -> Result<()>,
+{
+    let path = path.as_ref();
+    let original = fs::metadata(path).ok();
+    if let Some(metadata) = &original {
+        if !metadata.is_file() {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            return write(&mut file);
+        }
+    }
+    let temp_path = temp_path_for(path);
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        // the temporary file is an implementation detail, failing to create it means failing to write `path`
+        .map_err(|err| format!("{}: {}", path.display(), err).into())
+        .and_then(|mut file| {
+            write(&mut file)?;
+            // the new file replaces the original one, so it keeps its permissions
+            if let Some(metadata) = &original {
+                file.set_permissions(metadata.permissions())?;
+            }
+            // make sure the contents hit the disk before the rename makes them visible
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| {
+            fs::rename(&temp_path, path)
+                .map_err(|err| format!("{}: {}", path.display(), err).into())
+        });
+
+    if result.is_err() {
+        // best effort cleanup, the original error is what matters
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Returns a path of a hidden temporary file next to `path`.
+pub(crate) fn temp_path_for(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or(path.as_os_str()));
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
@@ -0,0 +1,65 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Lexically normalizes `path` by dropping `.` components and resolving `..` against preceding components.
+///
+/// The filesystem isn't consulted, so symlinks are not resolved. Leading `..` components of relative paths are kept.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // `..` of the root is the root itself
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                Some(Component::ParentDir) | Some(Component::CurDir) | None => {
+                    normalized.push(component)
+                }
+            },
+            _ => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(Component::CurDir);
+    }
+    normalized
+}
+
+/// Resolves `path` against `base` unless it's absolute already.
+pub(crate) fn absolute(base: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        normalize(&base.join(path))
+    }
+}
+
+/// Returns the directory containing the database file at `input`.
+pub(crate) fn input_directory(input: &Path) -> &Path {
+    match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Expresses the absolute `path` relative to the absolute `base`, both have to be normalized.
+pub(crate) fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    // skip the common prefix
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+    relative
+}
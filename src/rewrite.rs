@@ -0,0 +1,473 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::flags::takes_separate_value;
+use crate::paths::{absolute, input_directory, normalize, relative_to};
+use crate::{Entry, Result};
+
+/// How `directory`, `file` and `output` paths of the joined entries are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Paths are copied from the inputs as they are.
+    Keep,
+    /// Relative paths are resolved against the location of their input and written as absolute paths.
+    Absolute,
+    /// Paths within the directory of the joined database are written relative to it, so the database keeps working
+    /// when the whole tree is moved. See [`relativize_paths`].
+    Relative,
+}
+
+impl FromStr for PathStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(PathStyle::Keep),
+            "absolute" => Ok(PathStyle::Absolute),
+            "relative" => Ok(PathStyle::Relative),
+            _ => Err(format!(
+                "unknown path style `{}` (expected `absolute`, `relative` or `keep`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PathStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PathStyle::Keep => "keep",
+            PathStyle::Absolute => "absolute",
+            PathStyle::Relative => "relative",
+        })
+    }
+}
+
+/// Rewrites `directory`, `file` and `output` of all `entries` according to `style`.
+///
+/// Following the JSON Compilation Database format, a relative `directory` is relative to the database file the entry
+/// has been loaded from, while relative `file` and `output` are relative to the `directory`. Joining entries from
+/// databases in different locations breaks relative paths, so [`PathStyle::Absolute`] resolves them first.
+///
+/// [`PathStyle::Relative`] resolves paths the same way, turning them relative again has to wait until all other
+/// rewrites are done, with [`relativize_paths`].
+pub fn rewrite_paths(entries: &mut [Entry], style: PathStyle) -> Result<()> {
+    if style == PathStyle::Keep {
+        return Ok(());
+    }
+    let current_dir = std::env::current_dir()?;
+    for entry in entries {
+        let input_directory = absolute(&current_dir, input_directory(&entry.input));
+        let command = &mut entry.command;
+        command.directory = absolute(&input_directory, &command.directory);
+        command.file = absolute(&command.directory, &command.file);
+        if let Some(output) = &command.output {
+            command.output = Some(absolute(&command.directory, output));
+        }
+    }
+    Ok(())
+}
+
+/// Rule replacing a leading path prefix, written as `OLD=NEW`.
+///
+/// Prefixes match whole path components only, so `/src` matches `/src` and `/src/main.c` but not `/srcs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixMapping {
+    /// Prefix to replace.
+    pub from: String,
+
+    /// Replacement of the prefix.
+    pub to: String,
+}
+
+impl PrefixMapping {
+    /// Applies the mapping to `path` if it starts with [`PrefixMapping::from`].
+    pub fn apply(&self, path: &str) -> Option<String> {
+        let rest = path.strip_prefix(&self.from)?;
+        // only the root prefix ends with a separator, anything else has to be followed by one
+        let rest = match rest.strip_prefix('/') {
+            Some(rest) => rest,
+            None if rest.is_empty() || self.from.ends_with('/') => rest,
+            None => return None,
+        };
+        if rest.is_empty() {
+            Some(self.to.clone())
+        } else if self.to.ends_with('/') {
+            Some(format!("{}{}", self.to, rest))
+        } else {
+            Some(format!("{}/{}", self.to, rest))
+        }
+    }
+}
+
+impl FromStr for PrefixMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() => {
+                // `/src/` and `/src` are the same prefix, but keep the root as it is
+                let trim = |prefix: &str| match prefix.trim_end_matches('/') {
+                    "" => prefix.to_string(),
+                    trimmed => trimmed.to_string(),
+                };
+                Ok(PrefixMapping {
+                    from: trim(from),
+                    to: trim(to),
+                })
+            }
+            _ => Err(format!(
+                "invalid prefix mapping `{}` (expected `OLD=NEW`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PrefixMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.from, self.to)
+    }
+}
+
+/// Flags taking a path, either joined like `-I/usr/include` or as the next argument like `-isystem /usr/include`.
+const JOINED_PATH_FLAGS: &[&str] = &[
+    "-I",
+    "-iquote",
+    "-isystem",
+    "-idirafter",
+    "-iprefix",
+    "-iwithprefix",
+    "-iwithprefixbefore",
+    "-isysroot",
+    "-imacros",
+    "-include",
+    "-F",
+    "-L",
+    "-B",
+    "-o",
+    "-MF",
+    "/I",
+    "/FI",
+    "/Fo",
+];
+
+/// Rewrites `argument` if it's a path or a flag with a path value and `map` rewrites that path.
+fn rewrite_argument<F>(argument: &str, map: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    // a path on its own, e.g. the source file or the value of `-isystem <dir>`
+    if let Some(mapped) = map(argument) {
+        return Some(mapped);
+    }
+    // `--flag=path` like `--sysroot=/opt/sysroot`
+    if argument.starts_with('-') {
+        if let Some((flag, value)) = argument.split_once('=') {
+            if let Some(mapped) = map(value) {
+                return Some(format!("{}={}", flag, mapped));
+            }
+        }
+    }
+    rewrite_joined_path(argument, map)
+}
+
+/// Rewrites the path of a flag like `-Ipath` if `map` rewrites that path.
+fn rewrite_joined_path<F>(argument: &str, map: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    // longest flags first so `-include` isn't mistaken for `-I`
+    JOINED_PATH_FLAGS
+        .iter()
+        .filter_map(|flag| Some((*flag, argument.strip_prefix(flag)?)))
+        .filter(|(_, value)| !value.is_empty())
+        .max_by_key(|(flag, _)| flag.len())
+        .and_then(|(flag, value)| Some(format!("{}{}", flag, map(value)?)))
+}
+
+/// Rewrites the source `file` and the values of known path flags in `argv` with `map`, returns whether any changed.
+///
+/// Unlike [`rewrite_argument`] other arguments are left alone even if they look like paths, so neither the compiler
+/// nor values like `-DDATA_DIR=/usr/share/data` change.
+fn rewrite_path_arguments<F>(argv: &mut [String], file: &Path, map: F) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut changed = false;
+    let mut i = 1;
+    while i < argv.len() {
+        let argument = argv[i].as_str();
+        let is_path_flag = argument == "--sysroot" || JOINED_PATH_FLAGS.contains(&argument);
+        if is_path_flag || takes_separate_value(argument) {
+            if let Some(mapped) = argv
+                .get(i + 1)
+                .filter(|_| is_path_flag)
+                .and_then(|value| map(value))
+            {
+                argv[i + 1] = mapped;
+                changed = true;
+            }
+            i += 2;
+            continue;
+        }
+        let mapped = if Path::new(argument) == file {
+            map(argument)
+        } else if let Some(sysroot) = argument.strip_prefix("--sysroot=") {
+            map(sysroot).map(|mapped| format!("--sysroot={}", mapped))
+        } else {
+            rewrite_joined_path(argument, &map)
+        };
+        if let Some(mapped) = mapped {
+            argv[i] = mapped;
+            changed = true;
+        }
+        i += 1;
+    }
+    changed
+}
+
+/// Applies the first matching mapping of `mappings` to `path`, returns `None` if none match.
+fn map_path(path: &Path, mappings: &[PrefixMapping]) -> Option<PathBuf> {
+    let path = path.to_str()?;
+    mappings
+        .iter()
+        .find_map(|mapping| mapping.apply(path))
+        .map(PathBuf::from)
+}
+
+/// Replaces path prefixes of `directory`, `file`, `output` and path arguments of the compile command of all `entries`.
+///
+/// For every path the first matching mapping is used.
+pub fn map_prefixes(entries: &mut [Entry], mappings: &[PrefixMapping]) -> Result<()> {
+    if mappings.is_empty() {
+        return Ok(());
+    }
+    for entry in entries {
+        let mut argv = entry.argv()?;
+        let command = &mut entry.command;
+        if let Some(directory) = map_path(&command.directory, mappings) {
+            command.directory = directory;
+        }
+        if let Some(file) = map_path(&command.file, mappings) {
+            command.file = file;
+        }
+        if let Some(output) = command
+            .output
+            .as_deref()
+            .and_then(|output| map_path(output, mappings))
+        {
+            command.output = Some(output);
+        }
+
+        let mut changed = false;
+        for argument in argv.iter_mut() {
+            let map = |path: &str| mappings.iter().find_map(|mapping| mapping.apply(path));
+            if let Some(mapped) = rewrite_argument(argument, map) {
+                *argument = mapped;
+                changed = true;
+            }
+        }
+        // only rewrite the command when needed, so the original quoting is kept otherwise
+        if changed {
+            command.set_argv(argv);
+        }
+    }
+    Ok(())
+}
+
+/// Turns absolute paths within `base` relative, making the entries relocatable together with `base`.
+///
+/// `directory` becomes relative to `base`, which should be the directory containing the joined database. `file`,
+/// `output`, the source file argument and values of path flags like `-I` become relative to the `directory`, as that's
+/// where the compiler runs. Paths outside of `base` (like system headers) as well as all paths of entries with a
+/// `directory` outside of `base` stay as they are. Relative paths are expected to be resolved already, see
+/// [`rewrite_paths`].
+pub fn relativize_paths(entries: &mut [Entry], base: &Path) -> Result<()> {
+    let base = absolute(&std::env::current_dir()?, base);
+    for entry in entries {
+        let directory = normalize(&entry.command.directory);
+        if !directory.is_absolute() || !directory.starts_with(&base) {
+            continue;
+        }
+        let mut argv = entry.argv()?;
+        let command = &mut entry.command;
+        let relativize = |path: &Path| -> Option<PathBuf> {
+            let path = normalize(path);
+            (path.is_absolute() && path.starts_with(&base)).then(|| relative_to(&path, &directory))
+        };
+
+        let map = |path: &str| {
+            let relative = relativize(Path::new(path))?;
+            relative.to_str().map(str::to_string)
+        };
+        if rewrite_path_arguments(&mut argv, &command.file, map) {
+            command.set_argv(argv);
+        }
+
+        if let Some(file) = relativize(&command.file) {
+            command.file = file;
+        }
+        if let Some(output) = command.output.as_deref().and_then(relativize) {
+            command.output = Some(output);
+        }
+
+        command.directory = relative_to(&directory, &base);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix_mapping(rule: &str) -> PrefixMapping {
+        rule.parse().unwrap()
+    }
+
+    fn entry(directory: &str, file: &str, arguments: &[&str]) -> Entry {
+        Entry {
+            input: Path::new("/build/compile_commands.json").into(),
+            command: crate::CompileCommand {
+                directory: directory.into(),
+                file: file.into(),
+                command: None,
+                arguments: Some(arguments.iter().map(|arg| arg.to_string()).collect()),
+                output: None,
+            },
+        }
+    }
+
+    fn map_argument(argument: &str, rule: &str) -> Option<String> {
+        let mapping = prefix_mapping(rule);
+        rewrite_argument(argument, |path| mapping.apply(path))
+    }
+
+    #[test]
+    fn prefixes_match_whole_components() {
+        let mapping = prefix_mapping("/src=/home/user/repo");
+        assert_eq!(mapping.apply("/src").as_deref(), Some("/home/user/repo"));
+        assert_eq!(
+            mapping.apply("/src/a.c").as_deref(),
+            Some("/home/user/repo/a.c")
+        );
+        assert_eq!(mapping.apply("/srcs/a.c"), None);
+        assert_eq!(mapping.apply("src/a.c"), None);
+
+        // a trailing separator doesn't change the prefix
+        assert_eq!(prefix_mapping("/src/=/repo/"), prefix_mapping("/src=/repo"));
+    }
+
+    #[test]
+    fn root_prefix_keeps_separators() {
+        let mapping = prefix_mapping("/=/mnt");
+        assert_eq!(mapping.apply("/build").as_deref(), Some("/mnt/build"));
+        assert_eq!(mapping.apply("/").as_deref(), Some("/mnt"));
+
+        let mapping = prefix_mapping("/src=/");
+        assert_eq!(mapping.apply("/src/a b.c").as_deref(), Some("/a b.c"));
+        assert_eq!(mapping.apply("/src").as_deref(), Some("/"));
+
+        assert_eq!(prefix_mapping("/=/").apply("/a.c").as_deref(), Some("/a.c"));
+    }
+
+    #[test]
+    fn maps_path_arguments() {
+        let rule = "/src=/repo";
+        assert_eq!(map_argument("-I/src/x", rule).as_deref(), Some("-I/repo/x"));
+        assert_eq!(
+            map_argument("-include/src/pch.h", rule).as_deref(),
+            Some("-include/repo/pch.h")
+        );
+        assert_eq!(
+            map_argument("--sysroot=/src", rule).as_deref(),
+            Some("--sysroot=/repo")
+        );
+        // the separate value of `-isystem /src` is an argument on its own
+        assert_eq!(map_argument("-isystem", rule), None);
+        assert_eq!(map_argument("/src", rule).as_deref(), Some("/repo"));
+        assert_eq!(map_argument("-I/srcs/x", rule), None);
+    }
+
+    #[test]
+    fn maps_entry_paths_and_separate_values() {
+        let mut entries = [entry(
+            "/src/build",
+            "/src/a.c",
+            &[
+                "cc",
+                "-isystem",
+                "/src/include",
+                "-I/srcs",
+                "-c",
+                "/src/a.c",
+            ],
+        )];
+        map_prefixes(&mut entries, &[prefix_mapping("/src=/repo")]).unwrap();
+        let command = &entries[0].command;
+        assert_eq!(command.directory, Path::new("/repo/build"));
+        assert_eq!(command.file, Path::new("/repo/a.c"));
+        assert_eq!(
+            entries[0].argv().unwrap(),
+            [
+                "cc",
+                "-isystem",
+                "/repo/include",
+                "-I/srcs",
+                "-c",
+                "/repo/a.c"
+            ]
+        );
+    }
+
+    #[test]
+    fn relativizes_only_path_arguments() {
+        let mut entries = [entry(
+            "/tmp/play/build",
+            "/tmp/play/src/a.c",
+            &[
+                "/tmp/play/bin/cc",
+                "-DDATA_DIR=/tmp/play/data",
+                "-I/tmp/play/include",
+                "-isystem",
+                "/tmp/play/vendor",
+                "-I/usr/include",
+                "--sysroot=/tmp/play/sysroot",
+                "-o",
+                "/tmp/play/build/a.o",
+                "-c",
+                "/tmp/play/src/a.c",
+            ],
+        )];
+        relativize_paths(&mut entries, Path::new("/tmp/play")).unwrap();
+        let command = &entries[0].command;
+        assert_eq!(command.directory, Path::new("build"));
+        assert_eq!(command.file, Path::new("../src/a.c"));
+        assert_eq!(
+            entries[0].argv().unwrap(),
+            [
+                "/tmp/play/bin/cc",
+                "-DDATA_DIR=/tmp/play/data",
+                "-I../include",
+                "-isystem",
+                "../vendor",
+                "-I/usr/include",
+                "--sysroot=../sysroot",
+                "-o",
+                "a.o",
+                "-c",
+                "../src/a.c",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_entries_outside_of_base() {
+        let arguments = ["cc", "-I/tmp/play/include", "-c", "/tmp/play/a.c"];
+        let mut entries = [entry("/elsewhere", "/tmp/play/a.c", &arguments)];
+        relativize_paths(&mut entries, Path::new("/tmp/play")).unwrap();
+        assert_eq!(entries[0].command.directory, Path::new("/elsewhere"));
+        assert_eq!(entries[0].argv().unwrap(), arguments);
+    }
+}
@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::fmt;
+
+/// A command string which can't be split into words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellError {
+    reason: &'static str,
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid command: {}", self.reason)
+    }
+}
+
+impl std::error::Error for ShellError {}
+
+/// Splits `command` into words following POSIX shell quoting rules.
+///
+/// Words are separated by unquoted whitespace. Single quotes preserve everything up to the closing quote, double quotes
+/// preserve everything except `\` escaping `$`, `` ` ``, `"`, `\` and newlines, and an unquoted `\` escapes any
+/// character. Expansions (`$VAR`, globs, ...) are not performed.
+pub fn split(command: &str) -> Result<Vec<String>, ShellError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // distinguishes an empty quoted word (`''`) from no word at all
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => {
+                            return Err(ShellError {
+                                reason: "unterminated single quote",
+                            })
+                        }
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => {
+                                return Err(ShellError {
+                                    reason: "unterminated double quote",
+                                })
+                            }
+                        },
+                        Some(c) => word.push(c),
+                        None => {
+                            return Err(ShellError {
+                                reason: "unterminated double quote",
+                            })
+                        }
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // line continuation
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => {
+                    return Err(ShellError {
+                        reason: "trailing backslash",
+                    })
+                }
+            },
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Quotes `word` so a POSIX shell reads it back as a single word, words which don't need quoting are returned as is.
+pub fn quote(word: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        return Cow::Borrowed(word);
+    }
+    // everything is literal within single quotes, a single quote itself has to end the quoting, be escaped and start
+    // the quoting again
+    Cow::Owned(format!("'{}'", word.replace('\'', "'\\''")))
+}
+
+/// Joins `words` into a single command string, quoting them as needed.
+pub fn join<I, S>(words: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    words
+        .into_iter()
+        .map(|word| quote(word.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        split(command).unwrap()
+    }
+
+    #[test]
+    fn splits_on_unquoted_whitespace() {
+        assert_eq!(words("  cc\t-c \n a.c "), ["cc", "-c", "a.c"]);
+        assert!(words("").is_empty());
+        assert!(words(" \t\n").is_empty());
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(
+            words(r#"cc '-DX="a b"' 'c\d'"#),
+            ["cc", r#"-DX="a b""#, r"c\d"]
+        );
+        assert_eq!(words("a'b'c"), ["abc"]);
+        assert_eq!(words("''"), [""]);
+    }
+
+    #[test]
+    fn double_quotes_escape_only_special_characters() {
+        assert_eq!(
+            words(r#""-DX=\"y\"" "\$HOME" "a\b" "\\""#),
+            [r#"-DX="y""#, "$HOME", r"a\b", r"\"]
+        );
+        assert_eq!(words("\"a\\\nb\""), ["ab"]);
+        assert_eq!(words(r#""""#), [""]);
+    }
+
+    #[test]
+    fn backslash_escapes_any_character() {
+        assert_eq!(words(r"a\ b \' \\"), ["a b", "'", r"\"]);
+        assert_eq!(words("a \\\n b"), ["a", "b"]);
+    }
+
+    #[test]
+    fn reports_unterminated_quoting() {
+        assert!(split("'abc").is_err());
+        assert!(split("\"abc").is_err());
+        assert!(split("\"abc\\").is_err());
+        assert!(split("abc\\").is_err());
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("-DFOO=1"), "-DFOO=1");
+        assert_eq!(quote("/usr/include/c++"), "/usr/include/c++");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn join_round_trips_through_split() {
+        let cases: &[&[&str]] = &[
+            &["cc", "-c", "a.c"],
+            &[
+                "cc",
+                "-DX=\"a b\"",
+                "it's",
+                "",
+                "back\\slash",
+                "tab\there",
+                "new\nline",
+            ],
+            &["'", "\"", "\\", "$(rm -rf /)", "*.c", "~"],
+        ];
+        for &case in cases {
+            assert_eq!(split(&join(case)).unwrap(), case);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use tokio::sync::mpsc;
+
+use crate::discover::{canonicalize_location, dir_id, DirId};
+use crate::ignore::{IgnoreRules, IgnoreStack};
+use crate::marker::joined_marker_path;
+use crate::output::temp_path_for;
+use crate::{cache_path, DiscoverOptions, Result};
+
+/// `errno` of adding a watch beyond `fs.inotify.max_user_watches`.
+const ENOSPC: i32 = 28;
+
+/// Watches `roots` for changes of the compilation databases [`discover_with`](crate::discover_with) would find.
+///
+/// Every directory under the roots is watched with inotify, including directories created later (like new build
+/// directories), so creating, rewriting, moving or removing a database anywhere below the roots is noticed. Roots which
+/// are files are watched by themselves. Directories are skipped the same way the search skips them: ones matching
+/// [`DiscoverOptions::exclude`] or [`DiscoverOptions::ignore_files`] (as they are when watching starts) or deeper than
+/// [`DiscoverOptions::max_depth`] aren't watched, and symlinks to directories are only followed with
+/// [`DiscoverOptions::follow_symlinks`]. Only changes of databases the search would report count, so ones outside of
+/// the depth limits or not matching [`DiscoverOptions::include`] are left alone. Changes of
+/// [`DiscoverOptions::exclude_paths`] (along with their markers, caches and temporary files) are ignored, so writing
+/// the output doesn't trigger another run.
+///
+/// Changes are debounced: once something changes, paths of the changed databases (or of the roots, if the kernel
+/// dropped events) are sent over the returned channel only after nothing else changed for `debounce`, so a build
+/// rewriting many databases triggers a single update. Watching stops when the receiver is dropped. The initial
+/// directories are added on a blocking thread, still this has to be called from within a tokio runtime.
+pub async fn watch<I, P>(
+    roots: I,
+    options: DiscoverOptions,
+    debounce: Duration,
+) -> Result<mpsc::Receiver<Vec<PathBuf>>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let roots: Vec<PathBuf> = roots
+        .into_iter()
+        .map(|root| root.as_ref().to_path_buf())
+        .collect();
+    // adding the watches walks whole trees, so keep it off the runtime's threads
+    let watcher = tokio::task::spawn_blocking(move || Watcher::new(&roots, options)).await??;
+
+    // inotify blocks on reads, so it gets a thread of its own feeding the debouncing task
+    let (changes, mut changes_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || watcher.run(changes));
+
+    let (batches, batches_rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        while let Some(first) = changes_rx.recv().await {
+            let mut changed: Vec<PathBuf> = first;
+            // stops once nothing changes for a while, or the watching thread is gone
+            while let Ok(Some(more)) = tokio::time::timeout(debounce, changes_rx.recv()).await {
+                changed.extend(more);
+            }
+            changed.sort();
+            changed.dedup();
+            if batches.send(changed).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(batches_rx)
+}
+
+/// A watched directory.
+struct WatchedDir {
+    path: PathBuf,
+    /// The root this directory has been found under, `None` for parents of roots which are files.
+    root: Option<Arc<Path>>,
+    /// Number of directories between the root and this one, the root itself is at depth 0.
+    depth: usize,
+    /// Rules of ignore files found in this directory and its parents.
+    ignores: Option<Arc<IgnoreStack>>,
+    /// Identity of the directory, only tracked when following symlinks.
+    id: Option<DirId>,
+}
+
+struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, WatchedDir>,
+    /// Identities of watched directories, so following symlinks watches every directory only once.
+    visited: HashSet<DirId>,
+    /// Databases known to exist, to tell whether removing a directory removes any of them.
+    databases: HashSet<PathBuf>,
+    /// Paths never reported as changed.
+    ignored: HashSet<PathBuf>,
+    options: DiscoverOptions,
+}
+
+fn dir_mask() -> WatchMask {
+    WatchMask::CREATE
+        | WatchMask::CLOSE_WRITE
+        | WatchMask::MOVED_TO
+        | WatchMask::MOVED_FROM
+        | WatchMask::DELETE
+        | WatchMask::ONLYDIR
+}
+
+impl Watcher {
+    /// Watches `roots` and the directories below them.
+    fn new(roots: &[PathBuf], options: DiscoverOptions) -> Result<Self> {
+        let mut ignored = HashSet::new();
+        for path in &options.exclude_paths {
+            let path = canonicalize_location(path);
+            for sidecar in [joined_marker_path(&path), cache_path(&path)] {
+                ignored.insert(temp_path_for(&sidecar));
+                ignored.insert(sidecar);
+            }
+            ignored.insert(temp_path_for(&path));
+            ignored.insert(path);
+        }
+        let mut watcher = Watcher {
+            inotify: Inotify::init().map_err(|err| format!("can't initialize inotify: {}", err))?,
+            dirs: HashMap::new(),
+            visited: HashSet::new(),
+            databases: HashSet::new(),
+            ignored,
+            options,
+        };
+        for root in roots {
+            let canonical = root
+                .canonicalize()
+                .map_err(|err| format!("{}: {}", root.display(), err))?;
+            if canonical.is_dir() {
+                watcher.add_tree(&Arc::from(canonical.as_path()), canonical.clone(), 0, None)?;
+            } else {
+                watcher.add_file(canonical)?;
+            }
+        }
+        Ok(watcher)
+    }
+
+    fn dir_mask(&self) -> WatchMask {
+        if self.options.follow_symlinks {
+            dir_mask()
+        } else {
+            dir_mask() | WatchMask::DONT_FOLLOW
+        }
+    }
+
+    /// Watches `path` and all directories below it, returns databases found within.
+    ///
+    /// `ignores` are the rules of ignore files found in the parents of `path`.
+    fn add_tree(
+        &mut self,
+        root: &Arc<Path>,
+        path: PathBuf,
+        depth: usize,
+        ignores: Option<Arc<IgnoreStack>>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut pending = vec![(path, depth, ignores)];
+        while let Some((path, depth, ignores)) = pending.pop() {
+            let id = if self.options.follow_symlinks {
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => dir_id(&path, &metadata),
+                    Err(_) => continue,
+                }
+            } else {
+                None
+            };
+            if id.as_ref().is_some_and(|id| self.visited.contains(id)) {
+                // a cycle or another path to an already watched directory
+                continue;
+            }
+            let ignores = self.read_ignore_files(&path, ignores);
+            match self.inotify.watches().add(&path, self.dir_mask()) {
+                Ok(wd) => {
+                    self.visited.extend(id.iter().cloned());
+                    self.dirs.insert(
+                        wd,
+                        WatchedDir {
+                            path: path.clone(),
+                            root: Some(root.clone()),
+                            depth,
+                            ignores: ignores.clone(),
+                            id,
+                        },
+                    );
+                }
+                Err(err) if err.raw_os_error() == Some(ENOSPC) => {
+                    return Err(format!(
+                        "{}: too many directories to watch, raise `fs.inotify.max_user_watches`",
+                        path.display()
+                    )
+                    .into());
+                }
+                // gone already or not accessible, there's nothing to watch
+                Err(_) => continue,
+            }
+            let entries = match std::fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let is_dir = match entry.file_type() {
+                    Ok(file_type) if file_type.is_symlink() && self.options.follow_symlinks => {
+                        entry.path().is_dir()
+                    }
+                    Ok(file_type) => file_type.is_dir(),
+                    Err(_) => continue,
+                };
+                let child = entry.path();
+                if is_dir {
+                    if self
+                        .options
+                        .is_searched_dir(root, &ignores, &child, depth + 1)
+                    {
+                        pending.push((child, depth + 1, ignores.clone()));
+                    }
+                } else if self.options.is_reported_database(
+                    root,
+                    &ignores,
+                    &child,
+                    &entry.file_name(),
+                    depth + 1,
+                ) {
+                    self.databases.insert(child.clone());
+                    found.push(child);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Extends the ignore rules of the parents of `dir` with ignore files found in `dir`.
+    fn read_ignore_files(
+        &self,
+        dir: &Path,
+        mut ignores: Option<Arc<IgnoreStack>>,
+    ) -> Option<Arc<IgnoreStack>> {
+        for name in &self.options.ignore_files {
+            // unreadable ignore files are reported by the search already
+            if let Ok(contents) = std::fs::read_to_string(dir.join(name)) {
+                ignores =
+                    IgnoreStack::push(ignores, IgnoreRules::parse(dir.to_path_buf(), &contents));
+            }
+        }
+        ignores
+    }
+
+    /// Stops watching `path` and all directories below it, which are still there but no longer reachable from a root.
+    fn remove_tree(&mut self, path: &Path) {
+        let removed: Vec<WatchDescriptor> = self
+            .dirs
+            .iter()
+            .filter(|(_, dir)| dir.root.is_some() && dir.path.starts_with(path))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in removed {
+            if let Some(dir) = self.dirs.remove(&wd) {
+                if let Some(id) = &dir.id {
+                    self.visited.remove(id);
+                }
+            }
+            // fails for directories which are gone, their watches are removed already
+            let _ = self.inotify.watches().remove(wd);
+        }
+    }
+
+    /// Watches the directory containing the explicitly requested database `path`.
+    fn add_file(&mut self, path: PathBuf) -> Result<()> {
+        let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+        let wd = self
+            .inotify
+            .watches()
+            .add(parent, dir_mask())
+            .map_err(|err| format!("{}: {}", parent.display(), err))?;
+        self.dirs.entry(wd).or_insert_with(|| WatchedDir {
+            path: parent.to_path_buf(),
+            root: None,
+            depth: 0,
+            ignores: None,
+            id: None,
+        });
+        self.databases.insert(path);
+        Ok(())
+    }
+
+    /// Reads events until `changes` gets closed, sending paths of changed databases.
+    fn run(mut self, changes: mpsc::UnboundedSender<Vec<PathBuf>>) {
+        let mut buffer = [0; 4096];
+        loop {
+            let events: Vec<_> = match self.inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events.map(|event| event.to_owned()).collect(),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return,
+            };
+            let mut changed = Vec::new();
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    // events got lost, anything under the roots might have changed
+                    changed.extend(self.databases.iter().cloned());
+                    changed.extend(
+                        self.dirs
+                            .values()
+                            .filter(|dir| dir.root.is_some() && dir.depth == 0)
+                            .map(|dir| dir.path.clone()),
+                    );
+                    continue;
+                }
+                if event.mask.contains(EventMask::IGNORED) {
+                    // the directory is gone, its watch is removed automatically
+                    self.dirs.remove(&event.wd);
+                    continue;
+                }
+                if let Some(name) = &event.name {
+                    changed.extend(self.handle(&event.wd, event.mask, name));
+                }
+            }
+            if !changed.is_empty() && changes.send(changed).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Handles an event of an entry called `name` in the watched directory `wd`, returns changed databases.
+    fn handle(&mut self, wd: &WatchDescriptor, mask: EventMask, name: &OsStr) -> Vec<PathBuf> {
+        let (path, root, depth, ignores) = match self.dirs.get(wd) {
+            Some(dir) => (
+                dir.path.join(name),
+                dir.root.clone(),
+                dir.depth + 1,
+                dir.ignores.clone(),
+            ),
+            None => return Vec::new(),
+        };
+        if self.ignored.contains(&path) {
+            return Vec::new();
+        }
+
+        let added = mask.intersects(EventMask::CREATE | EventMask::MOVED_TO);
+        let removed = mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM);
+        // symlinks to directories don't count as directories for inotify
+        let is_dir = mask.contains(EventMask::ISDIR)
+            || (self.options.follow_symlinks
+                && root.is_some()
+                && ((added && path.is_dir())
+                    || (removed && self.dirs.values().any(|dir| dir.path.starts_with(&path)))));
+        if is_dir {
+            let root = match root {
+                Some(root) => root,
+                // only the requested files of this directory matter
+                None => return Vec::new(),
+            };
+            if added {
+                if !self.options.is_searched_dir(&root, &ignores, &path, depth) {
+                    return Vec::new();
+                }
+                // the directory might have been filled before its watch got added
+                return self
+                    .add_tree(&root, path, depth, ignores)
+                    .unwrap_or_default();
+            }
+            // a directory moved elsewhere or a removed symlink leaves the watched directories behind
+            self.remove_tree(&path);
+            // a removed directory takes all databases within along
+            let removed: Vec<PathBuf> = self
+                .databases
+                .iter()
+                .filter(|database| database.starts_with(&path))
+                .cloned()
+                .collect();
+            for database in &removed {
+                self.databases.remove(database);
+            }
+            return removed;
+        }
+
+        let relevant = match &root {
+            Some(root) => self
+                .options
+                .is_reported_database(root, &ignores, &path, name, depth),
+            None => self.databases.contains(&path),
+        };
+        if !relevant || mask.contains(EventMask::CREATE) {
+            // creating a file is followed by writing and closing it
+            return Vec::new();
+        }
+        if removed {
+            if root.is_some() {
+                self.databases.remove(&path);
+            }
+        } else {
+            self.databases.insert(path.clone());
+        }
+        vec![path]
+    }
+}